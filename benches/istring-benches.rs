@@ -28,9 +28,22 @@ fn a_bigger_bunch(c: &mut Criterion) {
     );
 }
 
+fn cloning_and_dropping(c: &mut Criterion) {
+    let my_istring = IString::from("A string that is cloned and dropped over and over");
+    c.bench_function(
+        "cloning and dropping an IString",
+        |bencher| {
+            bencher.iter(|| {
+                let my_clone = black_box(&my_istring).clone();
+                drop(black_box(my_clone));
+            })
+        }
+    );
+}
+
 criterion_group!(
     name = benches;
     config = Criterion::default();
-    targets = a_bigger_bunch
+    targets = a_bigger_bunch, cloning_and_dropping
 );
 criterion_main!(benches);
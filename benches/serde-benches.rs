@@ -0,0 +1,26 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use interned_string::IString;
+
+fn deserializing_repeated_values(c: &mut Criterion) {
+    let values = ["pending", "shipped", "delivered", "returned"];
+    let serialized = serde_json::to_string(&(0..10_000).map(|i| values[i % values.len()]).collect::<Vec<_>>()).unwrap();
+    // keep the values interned, like the values of an enum that are deserialized over and over
+    let _interned = values.map(IString::from);
+
+    c.bench_function(
+        "deserializing a large JSON array of repeated values",
+        |bencher| {
+            bencher.iter(|| {
+                let deserialized: Vec<IString> = serde_json::from_str(black_box(&serialized)).unwrap();
+                deserialized
+            })
+        }
+    );
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default();
+    targets = deserializing_repeated_values
+);
+criterion_main!(benches);
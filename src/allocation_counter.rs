@@ -0,0 +1,37 @@
+//! A global allocator for the tests, that counts the bytes allocated by each thread.
+
+use std::{alloc::{GlobalAlloc, Layout, System}, cell::Cell};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED_BYTES: Cell<isize> = const { Cell::new(0) };
+    static TOTAL_ALLOCATED_BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED_BYTES.try_with(|bytes| bytes.set(bytes.get() + layout.size() as isize));
+        let _ = TOTAL_ALLOCATED_BYTES.try_with(|bytes| bytes.set(bytes.get() + layout.size()));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = ALLOCATED_BYTES.try_with(|bytes| bytes.set(bytes.get() - layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The bytes allocated by this thread and not deallocated yet.
+pub(crate) fn allocated_bytes_in_this_thread() -> isize {
+    ALLOCATED_BYTES.with(Cell::get)
+}
+
+/// Including the bytes that were deallocated since.
+#[cfg(feature = "serde")]
+pub(crate) fn total_allocated_bytes_in_this_thread() -> usize {
+    TOTAL_ALLOCATED_BYTES.with(Cell::get)
+}
@@ -0,0 +1,123 @@
+use core::hash::{BuildHasherDefault, Hasher};
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "std")]
+use crate::IString;
+
+/// A `HashMap` keyed by [`IString`], that doesn't hash the keys with SipHash.
+///
+/// Each interned string has a unique `u32` key, so hashing it only takes a multiplication.
+/// It provides the whole `HashMap` API, and it is serializable with the `serde` feature.
+///
+/// # Example
+///
+/// ```
+/// use interned_string::{IString, IStringMap};
+///
+/// let mut ages: IStringMap<u32> = IStringMap::default();
+/// ages.insert(IString::from("alice"), 42);
+/// assert_eq!(ages.get(&IString::from("alice")), Some(&42));
+/// ```
+#[cfg(feature = "std")]
+pub type IStringMap<V> = HashMap<IString, V, BuildIStringHasher>;
+
+/// A `HashSet` of [`IString`], that doesn't hash the strings with SipHash.
+///
+/// See [`IStringMap`].
+#[cfg(feature = "std")]
+pub type IStringSet = HashSet<IString, BuildIStringHasher>;
+
+/// Creates [`IStringHasher`]s.
+pub type BuildIStringHasher = BuildHasherDefault<IStringHasher>;
+
+/// A hasher for the keys of `IString`s and `IStr`s, used by [`IStringMap`] and [`IStringSet`].
+///
+/// The keys are unique and dense, so they are only multiplied by a large odd constant,
+/// which spreads them over all the bits of the hash.
+/// It's not resistant to HashDoS, but the keys are not chosen by an attacker.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IStringHasher {
+    hash: u64,
+}
+
+impl IStringHasher {
+    /// 2^64 divided by the golden ratio, see Fibonacci hashing.
+    const MULTIPLIER: u64 = 0x9e37_79b9_7f4a_7c15;
+}
+
+impl Hasher for IStringHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    #[inline]
+    fn write_u32(&mut self, key: u32) {
+        self.write_u64(key as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, value: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ value).wrapping_mul(Self::MULTIPLIER);
+    }
+
+    /// Only called when hashing something else than an `IString`.
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::hash::BuildHasher;
+
+    use super::*;
+    use crate::tests::with_exclusive_use_of_shared_storage;
+
+    #[test]
+    fn it_maps_istrings() {
+        with_exclusive_use_of_shared_storage(|| {
+            let mut map: IStringMap<usize> = ["a", "b"].map(IString::from).into_iter().zip(0..).collect();
+            map.extend([(IString::from("c"), 2), (IString::from("a"), 3)]);
+
+            assert_eq!(map.len(), 3);
+            assert_eq!(map.get(&IString::from("a")), Some(&3));
+            assert_eq!(map.remove(&IString::from("b")), Some(1));
+            assert_eq!(map.get(&IString::from("b")), None);
+
+            let mut set: IStringSet = ["a", "b", "a"].map(IString::from).into_iter().collect();
+            set.extend([IString::from("c")]);
+            assert_eq!(set.len(), 3);
+            assert!(set.contains(&IString::from("b")));
+        });
+    }
+
+    #[test]
+    fn it_spreads_dense_keys() {
+        let build_hasher = BuildIStringHasher::default();
+        let hashes: HashSet<u64> = (0..1000u32).map(|key| build_hasher.hash_one(key)).collect();
+        assert_eq!(hashes.len(), 1000);
+        // the top bits are used by `HashMap` to filter the entries of a group
+        let top_bits: HashSet<u64> = hashes.iter().map(|hash| hash >> 57).collect();
+        assert_eq!(top_bits.len(), 128);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_serializes_and_deserializes() {
+        with_exclusive_use_of_shared_storage(|| {
+            let map: IStringMap<usize> = [(IString::from("pasta"), 1)].into_iter().collect();
+            let serialized = serde_json::to_string(&map).unwrap();
+            assert_eq!(serialized, "{\"pasta\":1}");
+
+            let deserialized: IStringMap<usize> = serde_json::from_str(&serialized).unwrap();
+            assert_eq!(deserialized, map);
+
+            let set: IStringSet = serde_json::from_str("[\"pasta\",\"pizza\"]").unwrap();
+            assert_eq!(set.len(), 2);
+        });
+    }
+}
@@ -0,0 +1,114 @@
+#[cfg(feature = "std")]
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Decides when the strings that are no longer used are freed.
+///
+/// Freeing unused strings acquires a lock and waits for all readers to finish reading,
+/// and a string that is freed must be inserted again the next time it is interned.
+/// Collecting less often is faster when the same strings are created and dropped over and over,
+/// at the cost of some memory.
+///
+/// Whatever the policy, [`IString::collect_garbage_now`](crate::IString::collect_garbage_now)
+/// immediately frees all the unused strings.
+///
+/// # Example
+///
+/// ```
+/// use interned_string::{GcPolicy, IString};
+///
+/// IString::set_gc_policy(GcPolicy::EveryNInserts(100));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum GcPolicy {
+    /// Free the unused strings every time a new string is interned.
+    #[default]
+    EveryInsert,
+    /// Free the unused strings once every N new strings are interned.
+    EveryNInserts(usize),
+    /// Free the unused strings when a new string is interned
+    /// and the unused strings occupy more than the given number of bytes.
+    UnusedBytesAbove(usize),
+    /// Only free the unused strings when `collect_garbage_now` is called.
+    Manual,
+    /// Free the unused strings every time a new string is interned,
+    /// except the given number of strings that were the most recently used.
+    KeepRecentlyUnused(usize),
+}
+
+impl GcPolicy {
+    /// The number of unused strings that are not freed by a background collection.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn kept_unused_strings(&self) -> usize {
+        match self {
+            GcPolicy::KeepRecentlyUnused(count) => *count,
+            _ => 0,
+        }
+    }
+}
+
+/// A thread that periodically frees the interned strings that are no longer used.
+///
+/// Without it, unused strings are only freed when a new string is interned
+/// or when the garbage is collected manually.
+/// The thread stops when this handle is dropped, or when [`BackgroundCollector::stop`] is called.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use interned_string::IString;
+///
+/// let collector = IString::start_background_collector(Duration::from_secs(10));
+/// // ...
+/// collector.stop();
+/// ```
+#[cfg(feature = "std")]
+#[must_use = "the background collector stops when it is dropped"]
+pub struct BackgroundCollector {
+    stop_sender: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "std")]
+impl BackgroundCollector {
+    /// Calls `collect` every `interval` in a new thread, until the collector is stopped.
+    pub(crate) fn spawn(interval: Duration, mut collect: impl FnMut() + Send + 'static) -> Self {
+        let (stop_sender, stop_receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("interned-string-gc".to_string())
+            .spawn(move || loop {
+                match stop_receiver.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => collect(),
+                    // stopped, or the handle went away
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+                }
+            })
+            .expect("failed to spawn the garbage collection thread");
+
+        Self { stop_sender: Some(stop_sender), thread: Some(thread) }
+    }
+
+    /// Stops the background thread, and waits for its current collection to finish.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for BackgroundCollector {
+    fn drop(&mut self) {
+        if let Some(stop_sender) = self.stop_sender.take() {
+            // Note: the thread may have panicked already, so the channel may be disconnected
+            let _ = stop_sender.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            // don't panic while dropping, the panic was already reported by the thread
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use alloc::string::String;
+use core::{
+    borrow::Borrow,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use crate::IString;
+
+/// An [`IString`] that hashes like its contents, so it can be used as the key of a map
+/// that is queried with `&str`.
+///
+/// `IString` hashes its key in O(1), so a `HashMap<IString, _>` can only be queried with `IString`s.
+/// `HashedIString` implements `Borrow<str>` instead, so a `HashMap<HashedIString, _>` can also be queried
+/// with a `&str` that was never interned, without acquiring any lock.
+/// The tradeoff is that hashing a `HashedIString` runs in O(N) where N is the length of the string,
+/// like hashing a `String`.
+///
+/// Equality is still checked in O(1).
+///
+/// The hash of the contents is also computed once when the `HashedIString` is created,
+/// see [`HashedIString::content_hash`].
+///
+/// With the `hashbrown` feature, a `hashbrown::HashMap<String, _>` can be queried with a `HashedIString`.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use interned_string::HashedIString;
+///
+/// let mut ages: HashMap<HashedIString, u32> = HashMap::new();
+/// ages.insert(HashedIString::from("alice"), 42);
+///
+/// assert_eq!(ages.get("alice"), Some(&42));
+/// assert_eq!(ages.get("bob"), None);
+/// ```
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HashedIString {
+    istring: IString,
+    content_hash: u64,
+}
+
+impl HashedIString {
+    /// Returns the hash of the contents of the string, that was computed when this `HashedIString` was created.
+    ///
+    /// Strings with the same contents always have the same content hash, with or without the `std` feature,
+    /// even if they are freed and interned again.
+    /// It's useful to shard the strings, or to compare hashes without hashing the contents again.
+    ///
+    /// This operation runs in O(1).
+    #[inline]
+    pub fn content_hash(&self) -> u64 {
+        self.content_hash
+    }
+
+    /// Returns the `IString` of this `HashedIString`.
+    #[inline]
+    pub fn as_istring(&self) -> &IString {
+        &self.istring
+    }
+
+    /// Converts this `HashedIString` back to an `IString`.
+    #[inline]
+    pub fn into_istring(self) -> IString {
+        self.istring
+    }
+}
+
+/// The hash of the contents of a string, see `HashedIString::content_hash`.
+///
+/// It's FNV-1a, that only depends on the contents, unlike the hashers of `std` that may change between releases.
+pub(crate) fn content_hash(string: &str) -> u64 {
+    string.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+impl From<IString> for HashedIString {
+    /// Hashes the contents of the given `IString` once, see [`HashedIString::content_hash`].
+    ///
+    /// This operation runs in O(N) where N is the `string.len()`.
+    #[inline]
+    fn from(istring: IString) -> Self {
+        let content_hash = content_hash(&istring);
+        Self { istring, content_hash }
+    }
+}
+
+impl From<String> for HashedIString {
+    /// See [`IString::from`].
+    #[inline]
+    fn from(string: String) -> Self {
+        Self::from(IString::from(string))
+    }
+}
+
+impl From<&str> for HashedIString {
+    /// See [`IString::from`].
+    #[inline]
+    fn from(string: &str) -> Self {
+        Self::from(IString::from(string))
+    }
+}
+
+impl From<HashedIString> for IString {
+    #[inline]
+    fn from(hashed: HashedIString) -> Self {
+        hashed.istring
+    }
+}
+
+impl Deref for HashedIString {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.istring
+    }
+}
+
+impl AsRef<str> for HashedIString {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.istring
+    }
+}
+
+impl Borrow<str> for HashedIString {
+    #[inline]
+    fn borrow(&self) -> &str {
+        &self.istring
+    }
+}
+
+impl Hash for HashedIString {
+    /// Hashes the contents of the string, like `str`.
+    ///
+    /// This operation runs in O(N) where N is the `string.len()`.
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.deref().hash(state)
+    }
+}
+
+impl PartialEq<str> for HashedIString {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.deref() == other
+    }
+}
+
+impl Debug for HashedIString {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("HashedIString")
+         .field(&self.deref())
+         .finish()
+    }
+}
+
+impl core::fmt::Display for HashedIString {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self)
+    }
+}
+
+#[cfg(feature = "hashbrown")]
+impl hashbrown::Equivalent<String> for HashedIString {
+    #[inline]
+    fn equivalent(&self, key: &String) -> bool {
+        self.deref() == key
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::collections::{hash_map::RandomState, HashMap, HashSet};
+    use std::hash::BuildHasher;
+
+    use super::*;
+    use crate::tests::with_exclusive_use_of_shared_storage;
+
+    #[test]
+    fn it_is_queried_with_str() {
+        with_exclusive_use_of_shared_storage(|| {
+            let mut map: HashMap<HashedIString, usize> = HashMap::new();
+            map.insert("hello".into(), 1);
+            map.insert("world".to_string().into(), 2);
+
+            assert_eq!(map.get("hello"), Some(&1));
+            assert_eq!(map.get("world"), Some(&2));
+            assert_eq!(map.get("howdy"), None);
+            // the query was not interned
+            assert!(!IString::contains("howdy"));
+
+            let set: HashSet<HashedIString> = ["a", "b", "a"].map(HashedIString::from).into_iter().collect();
+            assert_eq!(set.len(), 2);
+            assert!(set.contains("a"));
+        });
+    }
+
+    #[test]
+    fn it_keeps_the_content_hash() {
+        with_exclusive_use_of_shared_storage(|| {
+            let hello = HashedIString::from("hello");
+            assert_eq!(hello.content_hash(), content_hash("hello"));
+            assert_ne!(hello.content_hash(), content_hash("world"));
+
+            // the same contents have the same hash after they are freed and interned again
+            drop(hello);
+            IString::collect_garbage_now();
+            assert!(!IString::contains("hello"));
+            assert_eq!(HashedIString::from(IString::from("hello")).content_hash(), content_hash("hello"));
+
+            // it's FNV-1a, the same in every build
+            assert_eq!(HashedIString::from("a").content_hash(), 0xaf63_dc4c_8601_ec8c);
+        });
+    }
+
+    #[test]
+    fn it_hashes_like_str() {
+        with_exclusive_use_of_shared_storage(|| {
+            let hasher = RandomState::new();
+            let hello = HashedIString::from("hello");
+            assert_eq!(hasher.hash_one(&hello), hasher.hash_one("hello"));
+            assert_eq!(hello, *"hello");
+            assert_eq!(hello.clone().into_istring(), IString::from("hello"));
+        });
+    }
+
+    #[cfg(feature = "hashbrown")]
+    #[test]
+    fn it_queries_hashbrown_maps_of_strings() {
+        with_exclusive_use_of_shared_storage(|| {
+            let mut map: hashbrown::HashMap<String, usize, RandomState> = hashbrown::HashMap::default();
+            map.insert("hello".to_string(), 1);
+
+            assert_eq!(map.get(&HashedIString::from("hello")), Some(&1));
+            assert_eq!(map.get(&HashedIString::from("world")), None);
+        });
+    }
+}
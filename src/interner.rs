@@ -0,0 +1,808 @@
+use std::{fmt::Debug, hash::Hash, ops::Deref, ptr::NonNull, sync::{Arc, Mutex}, time::Duration};
+use once_cell::sync::{Lazy, OnceCell};
+use thread_local::ThreadLocal;
+
+use crate::gc::{BackgroundCollector, GcPolicy};
+use crate::stats::Stats;
+use crate::storage::{ConcurrentStringStorage, IStringKey, RetainedKey, ThreadLocalReader};
+
+/// A pool of interned strings.
+///
+/// `IString` uses a process-wide `Interner`.
+/// Create your own `Interner` when you want a separate pool of strings,
+/// for example one per tenant, per plugin or per test, that is freed all at once when dropped.
+///
+/// The strings of an `Interner` are [`IStr`]s, and they can't outlive it.
+///
+/// # Example
+///
+/// ```
+/// use interned_string::Interner;
+///
+/// let interner = Interner::new();
+/// let hello1 = interner.intern("hello");
+/// let hello2 = interner.intern("hello".to_string());
+/// assert_eq!(hello1, hello2);
+/// ```
+pub struct Interner {
+    /// One reader per thread that used this interner.
+    /// `None` for the shared interner, whose readers are in `THREAD_LOCAL_READER`.
+    /// Note: they are only dropped with the interner, so they send their releases immediately.
+    readers: Option<ThreadLocal<ThreadLocalReader>>,
+    /// The reader of the threads whose `THREAD_LOCAL_READER` was already destroyed, for the shared interner.
+    fallback_reader: OnceCell<Mutex<ThreadLocalReader>>,
+    pub(crate) storage: ConcurrentStringStorage,
+}
+
+impl Interner {
+    /// Creates a new and empty `Interner`.
+    pub fn new() -> Self {
+        Self {
+            readers: Some(ThreadLocal::new()),
+            fallback_reader: OnceCell::new(),
+            storage: ConcurrentStringStorage::new(),
+        }
+    }
+
+    /// Intern the given string in this `Interner`.
+    ///
+    /// This operation runs in O(N log M) where N is the `string.len()` and M is the number of interned strings.
+    /// If the string was already interned, this operation is lock-free.
+    /// Otherwise, a lock on this `Interner` is acquired.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use interned_string::Interner;
+    ///
+    /// let interner = Interner::new();
+    /// let my_istr = interner.intern("hello");
+    /// assert_eq!(&*my_istr, "hello");
+    /// ```
+    #[inline]
+    pub fn intern(&self, string: impl Into<String>) -> IStr<'_> {
+        // could block
+        IStr::new(self, self.insert_or_retain(string.into()))
+    }
+
+    /// Intern all the given strings in this `Interner`, and returns them in the same order.
+    ///
+    /// See [`IString::intern_many`](crate::IString::intern_many).
+    pub fn intern_many(&self, strings: impl IntoIterator<Item = impl Into<String>>) -> Vec<IStr<'_>> {
+        let strings = strings.into_iter().map(Into::into).collect();
+        self.insert_or_retain_many(strings)
+            .into_iter()
+            .map(|retained| IStr::new(self, retained))
+            .collect()
+    }
+
+    /// Returns the string with the given contents if it is interned in this `Interner`.
+    ///
+    /// Unlike [`Interner::intern`], this never inserts a new string and never acquires a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use interned_string::Interner;
+    ///
+    /// let interner = Interner::new();
+    /// let hello = interner.intern("hello");
+    /// assert_eq!(interner.get("hello"), Some(hello));
+    /// assert_eq!(interner.get("world"), None);
+    /// ```
+    #[inline]
+    pub fn get(&self, string: &str) -> Option<IStr<'_>> {
+        self.get_and_retain(string).map(|found| IStr::new(self, found))
+    }
+
+    /// Returns `true` if a string with the given contents is interned in this `Interner`.
+    ///
+    /// This operation is lock-free.
+    #[inline]
+    pub fn contains(&self, string: &str) -> bool {
+        self.with_reader(|tl_reader| tl_reader.contains(string))
+    }
+
+    /// Returns all the strings interned in this `Interner` that start with the given prefix,
+    /// in lexicographic order.
+    ///
+    /// See [`IString::with_prefix`](crate::IString::with_prefix).
+    pub fn with_prefix(&self, prefix: &str) -> Vec<IStr<'_>> {
+        self.with_reader(|tl_reader| tl_reader.get_and_retain_with_prefix(prefix))
+            .into_iter()
+            .map(|found| IStr::new(self, found))
+            .collect()
+    }
+
+    /// Returns the longest string interned in this `Interner` that is a prefix of the given string.
+    ///
+    /// See [`IString::longest_interned_prefix_of`](crate::IString::longest_interned_prefix_of).
+    pub fn longest_interned_prefix_of(&self, string: &str) -> Option<IStr<'_>> {
+        self.with_reader(|tl_reader| tl_reader.get_and_retain_longest_prefix_of(string))
+            .map(|found| IStr::new(self, found))
+    }
+
+    /// Returns every string interned in this `Interner` with its reference count, in lexicographic order.
+    ///
+    /// See [`IString::interned_strings`](crate::IString::interned_strings).
+    pub fn interned_strings(&self) -> impl Iterator<Item = (IStr<'_>, usize)> {
+        self.with_reader(|tl_reader| tl_reader.get_and_retain_all())
+            .into_iter()
+            .map(|(found, reference_count)| (IStr::new(self, found), reference_count))
+    }
+
+    /// Immediately frees all the strings of this `Interner` that are no longer used.
+    ///
+    /// See [`IString::collect_garbage_now`](crate::IString::collect_garbage_now).
+    pub fn collect_garbage_now(&self) {
+        self.storage.writer.lock().unwrap().collect_garbage();
+    }
+
+    /// Returns the garbage collection policy of this `Interner`.
+    pub fn gc_policy(&self) -> GcPolicy {
+        self.storage.writer.lock().unwrap().gc_policy()
+    }
+
+    /// Changes when the strings of this `Interner` that are no longer used are freed.
+    ///
+    /// See [`GcPolicy`].
+    pub fn set_gc_policy(&self, gc_policy: GcPolicy) {
+        self.storage.writer.lock().unwrap().set_gc_policy(gc_policy);
+    }
+
+    /// Starts a thread that frees the strings of this `Interner` that are no longer used,
+    /// every `interval`.
+    ///
+    /// The thread keeps the `Interner` alive until the returned [`BackgroundCollector`] is dropped.
+    ///
+    /// See [`IString::start_background_collector`](crate::IString::start_background_collector).
+    pub fn start_background_collector(self: &Arc<Self>, interval: Duration) -> BackgroundCollector {
+        let interner = Arc::clone(self);
+        BackgroundCollector::spawn(interval, move || interner.collect_garbage_in_background())
+    }
+
+    /// Returns a snapshot of the state of this `Interner`.
+    ///
+    /// See [`IString::stats`](crate::IString::stats).
+    pub fn stats(&self) -> Stats {
+        self.storage.writer.lock().unwrap().stats()
+    }
+
+    fn shared() -> Self {
+        Self {
+            readers: None,
+            fallback_reader: OnceCell::new(),
+            storage: ConcurrentStringStorage::new(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn with_reader<R>(&self, f: impl FnOnce(&ThreadLocalReader) -> R) -> R {
+        match &self.readers {
+            None => {
+                // Note: strings can still be cloned and dropped by the destructors of other thread locals
+                // after `THREAD_LOCAL_READER` was destroyed
+                let mut f = Some(f);
+                match THREAD_LOCAL_READER.try_with(|tl_reader| f.take().unwrap()(tl_reader)) {
+                    Ok(result) => result,
+                    Err(_) => self.with_fallback_reader(f.take().unwrap()),
+                }
+            },
+            Some(readers) => f(readers.get_or(|| ThreadLocalReader::from(&self.storage))),
+        }
+    }
+
+    /// Calls `f` with the reader that is shared by the threads that can't use their own reader.
+    #[cold]
+    fn with_fallback_reader<R>(&self, f: impl FnOnce(&ThreadLocalReader) -> R) -> R {
+        // Note: it is never dropped, so it sends its releases immediately
+        let fallback_reader = self.fallback_reader.get_or_init(|| Mutex::new(ThreadLocalReader::from(&self.storage)));
+        f(&fallback_reader.lock().unwrap())
+    }
+
+    #[inline]
+    pub(crate) fn insert_or_retain(&self, string: String) -> RetainedKey {
+        self.with_reader(|tl_reader| self.storage.insert_or_retain(tl_reader, string))
+    }
+
+    /// Like `insert_or_retain`, for a string that was just looked up and not found.
+    pub(crate) fn insert(&self, string: String) -> RetainedKey {
+        self.storage.insert(string.into())
+    }
+
+    pub(crate) fn insert_or_retain_many(&self, strings: Vec<String>) -> Vec<RetainedKey> {
+        self.storage.insert_or_retain_many(strings)
+    }
+
+    #[inline]
+    pub(crate) fn get_and_retain(&self, string: &str) -> Option<RetainedKey> {
+        self.with_reader(|tl_reader| tl_reader.get_and_retain(string))
+    }
+
+    pub(crate) fn collect_garbage_in_background(&self) {
+        self.storage.writer.lock().unwrap().collect_garbage_in_background();
+    }
+
+    pub(crate) fn pin(&self, key: IStringKey) {
+        self.storage.writer.lock().unwrap().pin(key);
+    }
+}
+
+impl Default for Interner {
+    /// Creates a new and empty `Interner`.
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An immutable string interned in an [`Interner`].
+///
+/// It provides the same features as `IString`, but it can't outlive its `Interner`.
+///
+/// Two `IStr`s are equal if they have the same contents and belong to the same `Interner`.
+pub struct IStr<'i> {
+    interner: &'i Interner,
+    key: IStringKey,
+    is_pinned: bool,
+    /// The contents of the stored string, that live at least as long as this `IStr`.
+    contents: NonNull<str>,
+}
+
+// Safety: the contents are an immutable `str`, that is not freed while the `IStr` lives.
+unsafe impl Send for IStr<'_> {}
+unsafe impl Sync for IStr<'_> {}
+
+impl<'i> IStr<'i> {
+    #[inline]
+    fn new(interner: &'i Interner, retained: RetainedKey) -> Self {
+        Self { interner, key: retained.key, is_pinned: retained.is_pinned, contents: retained.contents }
+    }
+
+    /// Returns the `Interner` this string belongs to.
+    #[inline]
+    pub fn interner(&self) -> &'i Interner {
+        self.interner
+    }
+
+    /// Marks the string as permanent: it will never be freed, even if it is no longer used,
+    /// until the `Interner` is dropped.
+    ///
+    /// See [`IString::pin`](crate::IString::pin).
+    pub fn pin(&mut self) {
+        if !self.is_pinned {
+            self.interner.pin(self.key);
+            // the reference count of a pinned string no longer matters,
+            // so this IStr keeps its retain forever
+            self.is_pinned = true;
+        }
+    }
+}
+
+impl Drop for IStr<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        if self.is_pinned {
+            return;
+        }
+        self.interner.with_reader(|tl_reader| {
+            tl_reader.release(self.key);
+        });
+    }
+}
+
+impl Deref for IStr<'_> {
+    type Target = str;
+
+    /// Returns a reference to the string's contents.
+    ///
+    /// This operation runs in O(1) and is lock-free.
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // Safety: self is retained and lives longer than the returned reference.
+        unsafe { self.contents.as_ref() }
+    }
+}
+
+impl AsRef<str> for IStr<'_> {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl Clone for IStr<'_> {
+    /// Returns a copy of the `IStr`.
+    ///
+    /// This operation runs in O(1) and is lock-free.
+    #[inline]
+    fn clone(&self) -> Self {
+        if !self.is_pinned {
+            self.interner.with_reader(|tl_reader| {
+                tl_reader.retain(self.key)
+            });
+        }
+
+        Self { interner: self.interner, key: self.key, is_pinned: self.is_pinned, contents: self.contents }
+    }
+}
+
+impl PartialEq for IStr<'_> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && std::ptr::eq(self.interner, other.interner)
+    }
+}
+
+impl Eq for IStr<'_> {}
+
+impl Hash for IStr<'_> {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key.hash(state)
+    }
+}
+
+impl PartialOrd for IStr<'_> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IStr<'_> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // the same contents in two `Interner`s are not equal, so they are ordered by `Interner`
+        self.deref().cmp(other.deref())
+            .then_with(|| (self.interner as *const Interner).cmp(&(other.interner as *const Interner)))
+    }
+}
+
+impl Debug for IStr<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("IStr")
+         .field(&self.deref())
+         .finish()
+    }
+}
+
+impl std::fmt::Display for IStr<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self)
+    }
+}
+
+/// The interner used by `IString`.
+pub(crate) static SHARED_INTERNER: Lazy<Interner> = Lazy::new(Interner::shared);
+
+thread_local! {
+    // Note: it is dropped when the thread exits, so it can keep the pending releases
+    pub(crate) static THREAD_LOCAL_READER: ThreadLocalReader = ThreadLocalReader::with_pending_releases(&SHARED_INTERNER.storage);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::allocation_counter;
+    use crate::storage::MAX_PENDING_CHANNEL_OPS;
+
+    #[test]
+    fn it_interns_in_separate_pools() {
+        let interner1 = Interner::new();
+        let interner2 = Interner::new();
+
+        let hello1 = interner1.intern("hello");
+        let world1 = interner1.intern("world");
+        let hello2 = interner2.intern("hello");
+
+        assert_eq!(&*hello1, "hello");
+        assert_eq!(&*world1, "world");
+        assert_eq!(&*hello2, "hello");
+
+        assert_eq!(hello1, interner1.intern("hello"));
+        assert_ne!(hello1, world1);
+        // same key, but not the same interner
+        assert_eq!(hello1.key, hello2.key);
+        assert_ne!(hello1, hello2);
+
+        assert_eq!(string_count_in_storage(&interner1), 2);
+        assert_eq!(string_count_in_storage(&interner2), 1);
+    }
+
+    #[test]
+    fn it_collects_garbage() {
+        let interner = Interner::new();
+        let hello = interner.intern("hello");
+        let world = interner.intern("world");
+        drop(hello);
+
+        interner.collect_garbage_now();
+
+        assert_eq!(string_count_in_storage(&interner), 1);
+        assert_eq!(&*world, "world");
+    }
+
+    #[test]
+    fn it_is_shared_between_threads() {
+        let interner = Interner::new();
+        let hello = interner.intern("hello");
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let hello_clone = hello.clone();
+                    assert_eq!(&*hello_clone, "hello");
+                    assert_eq!(hello_clone, interner.intern("hello"));
+                    interner.intern("world");
+                });
+            }
+        });
+
+        assert_eq!(&*hello, "hello");
+        interner.collect_garbage_now();
+        assert_eq!(string_count_in_storage(&interner), 1);
+    }
+
+    #[test]
+    fn it_interns_many_strings_at_once() {
+        let interner = Interner::new();
+        let hello = interner.intern("hello");
+        let stats = interner.stats();
+
+        let istrs = interner.intern_many(["a", "hello", "b", "a"]);
+        assert_eq!(istrs.iter().map(|s| s.deref()).collect::<Vec<_>>(), ["a", "hello", "b", "a"]);
+        assert_eq!(istrs[1], hello);
+        assert_eq!(istrs[0], istrs[3]);
+        assert_eq!(string_count_in_storage(&interner), 3);
+        // published once, without collecting the garbage
+        assert_eq!(interner.stats().publishes, stats.publishes + 1);
+        assert_eq!(interner.stats().gc_runs, stats.gc_runs);
+
+        // the strings that were all found are retained by a publish too
+        let found = interner.intern_many(["a", "b"]);
+        assert_eq!(interner.stats().publishes, stats.publishes + 2);
+        let reference_counts: Vec<_> = interner.interned_strings().map(|(istr, count)| (istr.to_string(), count)).collect();
+        assert_eq!(reference_counts, [("a".to_string(), 3), ("b".to_string(), 2), ("hello".to_string(), 2)]);
+        drop(found);
+
+        // each duplicate is retained
+        drop(istrs);
+        drop(hello);
+        interner.collect_garbage_now();
+        assert_eq!(string_count_in_storage(&interner), 0);
+    }
+
+    #[test]
+    fn it_never_frees_a_string_while_a_reader_retains_it() {
+        let interner = Interner::new();
+
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let interner = &interner;
+                scope.spawn(move || {
+                    let mut held = std::collections::VecDeque::new();
+                    for index in 0..20_000 {
+                        // the same few strings are found, dropped and collected by every thread
+                        let string = format!("shared-{}", (index * 7 + thread) % 64);
+                        let istr = interner.intern(string.as_str());
+                        assert_eq!(&*istr, string);
+                        held.push_back((istr.clone(), string));
+                        if held.len() > 16 {
+                            held.pop_front();
+                        }
+                        for (istr, string) in &held {
+                            assert_eq!(&**istr, string);
+                        }
+                        if index % 64 == thread {
+                            interner.collect_garbage_now();
+                        }
+                    }
+                });
+            }
+        });
+
+        interner.collect_garbage_now();
+        assert_eq!(string_count_in_storage(&interner), 0);
+    }
+
+    #[test]
+    fn it_looks_up_without_inserting() {
+        let interner = Interner::new();
+        let hello = interner.intern("hello");
+
+        assert!(interner.contains("hello"));
+        assert!(!interner.contains("world"));
+        assert_eq!(interner.get("hello"), Some(hello));
+        assert_eq!(interner.get("world"), None);
+        assert_eq!(string_count_in_storage(&interner), 1);
+    }
+
+    #[test]
+    fn it_iterates_over_interned_strings() {
+        let interner = Interner::new();
+        let world = interner.intern("world");
+        let hello1 = interner.intern("hello");
+        let _hello2 = hello1.clone();
+        // apply the pending reference count updates
+        interner.collect_garbage_now();
+
+        let interned: Vec<(String, usize)> = interner.interned_strings()
+            .map(|(string, reference_count)| (string.to_string(), reference_count))
+            .collect();
+        assert_eq!(interned, [("hello".to_string(), 2), ("world".to_string(), 1)]);
+
+        drop(world);
+        interner.collect_garbage_now();
+        let interned: Vec<String> = interner.interned_strings().map(|(string, _)| string.to_string()).collect();
+        assert_eq!(interned, ["hello"]);
+    }
+
+    #[test]
+    fn it_finds_strings_by_prefix() {
+        let interner = Interner::new();
+        let strings = ["user.name", "user", "users", "user.id", "group.id", "usé", "usée"].map(|s| interner.intern(s));
+
+        let with_prefix = |prefix| interner.with_prefix(prefix).iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(with_prefix("user."), ["user.id", "user.name"]);
+        assert_eq!(with_prefix("user"), ["user", "user.id", "user.name", "users"]);
+        assert_eq!(with_prefix("usé"), ["usé", "usée"]);
+        assert_eq!(with_prefix("user.a"), Vec::<String>::new());
+        assert_eq!(with_prefix("").len(), strings.len());
+
+        let longest_prefix_of = |string| interner.longest_interned_prefix_of(string).map(|s| s.to_string());
+        assert_eq!(longest_prefix_of("user.name.first").as_deref(), Some("user.name"));
+        assert_eq!(longest_prefix_of("user.email").as_deref(), Some("user"));
+        assert_eq!(longest_prefix_of("user").as_deref(), Some("user"));
+        assert_eq!(longest_prefix_of("usées").as_deref(), Some("usée"));
+        assert_eq!(longest_prefix_of("use"), None);
+        assert_eq!(longest_prefix_of("group"), None);
+    }
+
+    #[test]
+    fn it_never_frees_pinned_strings() {
+        let interner = Interner::new();
+        let mut hello = interner.intern("hello");
+        hello.pin();
+        drop(hello);
+        interner.collect_garbage_now();
+
+        let hello = interner.get("hello").unwrap();
+        assert!(hello.is_pinned);
+        assert_eq!(&*hello, "hello");
+        assert_eq!(string_count_in_storage(&interner), 1);
+    }
+
+    #[test]
+    fn it_collects_garbage_every_n_inserts() {
+        let interner = Interner::new();
+        interner.set_gc_policy(GcPolicy::EveryNInserts(3));
+        assert_eq!(interner.gc_policy(), GcPolicy::EveryNInserts(3));
+
+        drop(interner.intern("a"));
+        drop(interner.intern("b"));
+        assert_eq!(string_count_in_storage(&interner), 2);
+
+        // the third insert collects "a" and "b"
+        let c = interner.intern("c");
+        assert_eq!(string_count_in_storage(&interner), 1);
+        assert!(interner.contains(&c));
+    }
+
+    #[test]
+    fn it_collects_garbage_when_unused_bytes_exceed_a_threshold() {
+        let interner = Interner::new();
+        interner.set_gc_policy(GcPolicy::UnusedBytesAbove(5));
+
+        drop(interner.intern("abc"));
+        drop(interner.intern("def"));
+        assert_eq!(string_count_in_storage(&interner), 2);
+
+        // 6 unused bytes
+        let _ghi = interner.intern("ghi");
+        assert_eq!(string_count_in_storage(&interner), 1);
+        assert!(!interner.contains("abc"));
+        assert!(!interner.contains("def"));
+    }
+
+    #[test]
+    fn it_only_waits_for_the_readers_again_when_it_frees_strings() {
+        let interner = Interner::new();
+        let hello = interner.intern("hello");
+
+        // nothing is unused, the default policy unlinks nothing in the publish of the insert
+        let publishes = interner.stats().publishes;
+        let _world = interner.intern("world");
+        assert_eq!(interner.stats().publishes, publishes + 1);
+
+        // the insert unlinks "hello", then waits for the readers that may have found it before dropping it
+        drop(hello);
+        let _abc = interner.intern("abc");
+        let stats = interner.stats();
+        assert_eq!(stats.publishes, publishes + 4);
+        assert_eq!(stats.gc_runs, 1);
+        assert!(!interner.contains("hello"));
+        assert_eq!(string_count_in_storage(&interner), 2);
+    }
+
+    #[test]
+    fn it_collects_garbage_manually() {
+        let interner = Interner::new();
+        interner.set_gc_policy(GcPolicy::Manual);
+
+        for i in 0..10 {
+            drop(interner.intern(format!("{i}")));
+        }
+        assert_eq!(string_count_in_storage(&interner), 10);
+
+        interner.collect_garbage_now();
+        assert_eq!(string_count_in_storage(&interner), 0);
+    }
+
+    #[test]
+    fn it_keeps_recently_unused_strings() {
+        let interner = Interner::new();
+        interner.set_gc_policy(GcPolicy::KeepRecentlyUnused(2));
+
+        drop(interner.intern("a"));
+        drop(interner.intern("b"));
+        let c = interner.intern("c");
+        assert_eq!(string_count_in_storage(&interner), 3);
+
+        // "a" is the least recently used
+        drop(c);
+        let d = interner.intern("d");
+        assert_eq!(string_count_in_storage(&interner), 3);
+        assert!(!interner.contains("a"));
+
+        // "b" is used again, so "c" becomes the least recently used
+        drop(interner.get("b").unwrap());
+        drop(d);
+        let _e = interner.intern("e");
+        assert_eq!(string_count_in_storage(&interner), 3);
+        assert!(interner.contains("b"));
+        assert!(!interner.contains("c"));
+        assert!(interner.contains("d"));
+
+        // unless the garbage is collected manually
+        interner.collect_garbage_now();
+        assert_eq!(string_count_in_storage(&interner), 1);
+        assert!(interner.contains("e"));
+    }
+
+    #[test]
+    fn it_collects_garbage_in_the_background() {
+        let interner = Arc::new(Interner::new());
+        interner.set_gc_policy(GcPolicy::Manual);
+        let collector = interner.start_background_collector(Duration::from_millis(1));
+
+        let hello = interner.intern("hello");
+        drop(interner.intern("world"));
+
+        // no string is inserted anymore, the thread still collects "world"
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while interner.contains("world") {
+            assert!(std::time::Instant::now() < deadline, "the background collector did not run");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(interner.contains(&hello));
+
+        collector.stop();
+        assert_eq!(Arc::strong_count(&interner), 1);
+    }
+
+    #[test]
+    fn it_reports_stats() {
+        let interner = Interner::new();
+        interner.set_gc_policy(GcPolicy::Manual);
+        assert_eq!(interner.stats(), Stats::default());
+
+        let hello = interner.intern("hello");
+        drop(interner.intern("world"));
+        let stats = interner.stats();
+        assert_eq!(stats.strings, 2);
+        assert_eq!(stats.bytes, 10);
+        // the Release of "world" was not received yet
+        assert_eq!(stats.pending_ops, 1);
+        assert_eq!(stats.unused_strings, 0);
+        assert_eq!(stats.keys_issued, 2);
+        assert_eq!(stats.gc_runs, 0);
+        assert!(stats.last_publish_duration.is_some());
+
+        drop(hello);
+        let _abc = interner.intern("abc");
+        let stats = interner.stats();
+        assert_eq!(stats.strings, 3);
+        assert_eq!(stats.pending_ops, 0);
+        assert_eq!(stats.unused_strings, 2);
+        assert_eq!(stats.unused_bytes, 10);
+
+        interner.collect_garbage_now();
+        let stats = interner.stats();
+        assert_eq!(stats.strings, 1);
+        assert_eq!(stats.bytes, 3);
+        assert_eq!(stats.unused_strings, 0);
+        assert_eq!(stats.unused_bytes, 0);
+        assert_eq!(stats.keys_issued, 3);
+        assert_eq!(stats.gc_runs, 1);
+    }
+
+    #[test]
+    fn it_bounds_the_pending_ops() {
+        let interner = Interner::new();
+        let hello = interner.intern("hello");
+        let publishes = interner.stats().publishes;
+
+        // no string is inserted, so the readers receive the Retain operations themselves, without publishing them
+        let clones: Vec<IStr> = (0..2 * MAX_PENDING_CHANNEL_OPS).map(|_| hello.clone()).collect();
+        let stats = interner.stats();
+        assert_eq!(stats.pending_ops, MAX_PENDING_CHANNEL_OPS);
+        assert_eq!(stats.reader_drains, 1);
+        assert_eq!(stats.publishes, publishes);
+
+        drop(clones);
+        drop(hello);
+        interner.collect_garbage_now();
+        assert_eq!(interner.stats().strings, 0);
+    }
+
+    #[test]
+    fn it_stores_each_string_once() {
+        const STRING_COUNT: usize = 1000;
+
+        /// The bytes that remain allocated per interned string, besides one copy of its contents.
+        fn bookkeeping_per_string(string_len: usize) -> isize {
+            let interner = Interner::new();
+            interner.set_gc_policy(GcPolicy::Manual);
+            // create the reader of this thread
+            drop(interner.intern("warm up"));
+
+            let allocated_before = allocation_counter::allocated_bytes_in_this_thread();
+            let strings: Vec<String> = (0..STRING_COUNT)
+                .map(|i| format!("{i:0>string_len$}").into_boxed_str().into())
+                .collect();
+            let istrs: Vec<_> = strings.into_iter().map(|string| interner.intern(string)).collect();
+            let allocated = allocation_counter::allocated_bytes_in_this_thread() - allocated_before;
+            assert_eq!(istrs.len(), STRING_COUNT);
+            (allocated - (STRING_COUNT * string_len) as isize) / STRING_COUNT as isize
+        }
+
+        // The contents of the strings are moved in storage, and aliased by both copies and by the index.
+        // So the bookkeeping doesn't depend on the length of the strings, even partial copies would show up.
+        let short = bookkeeping_per_string(16);
+        let long = bookkeeping_per_string(4096);
+        assert!((long - short).abs() < 16, "{short} bytes of bookkeeping per string of 16 bytes, {long} per string of 4096 bytes");
+    }
+
+    #[test]
+    fn it_orders_by_contents() {
+        let interner = Interner::new();
+        let mut strings = [interner.intern("b"), interner.intern("c"), interner.intern("a")];
+        strings.sort();
+        assert_eq!(strings.iter().map(|s| &**s).collect::<Vec<_>>(), ["a", "b", "c"]);
+
+        let other_interner = Interner::new();
+        let hello = interner.intern("hello");
+        let other_hello = other_interner.intern("hello");
+        assert_ne!(hello, other_hello);
+        assert_ne!(hello.cmp(&other_hello), std::cmp::Ordering::Equal);
+        assert_eq!(hello.cmp(&other_hello), other_hello.cmp(&hello).reverse());
+        assert!(hello > interner.intern("a") && other_hello > interner.intern("a"));
+    }
+
+    #[test]
+    fn it_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Interner>();
+        assert_send_sync::<IStr<'static>>();
+    }
+
+    fn string_count_in_storage(interner: &Interner) -> usize {
+        let guard = interner.storage.read_handle.lock().unwrap();
+        let read_handle = guard.enter().unwrap();
+        assert_eq!(read_handle.map.len(), read_handle.index.len());
+        read_handle.map.len()
+    }
+}
@@ -1,24 +1,84 @@
-use std::{fmt::Debug, ops::Deref};
-use storage::{IStringKey, ThreadLocalReader, SHARED_STORAGE, THREAD_LOCAL_READER};
-
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt::Debug, ops::Deref, ptr::NonNull};
+#[cfg(feature = "std")]
+use std::time::Duration;
+#[cfg(feature = "std")]
+use interner::SHARED_INTERNER;
+#[cfg(not(feature = "std"))]
+use storage::local::SHARED_INTERNER;
+use storage::{IStringKey, RetainedKey};
+
+pub use collections::{BuildIStringHasher, IStringHasher};
+#[cfg(feature = "std")]
+pub use collections::{IStringMap, IStringSet};
+#[cfg(feature = "std")]
+pub use gc::BackgroundCollector;
+pub use gc::GcPolicy;
+pub use hashed::HashedIString;
+#[cfg(feature = "std")]
+pub use interner::{IStr, Interner};
+pub use stats::Stats;
+pub use symbol::Symbol;
+pub use weak::WeakIString;
+
+#[cfg(all(test, feature = "std"))]
+mod allocation_counter;
+mod collections;
+mod gc;
+mod hashed;
+#[cfg(feature = "std")]
+mod interner;
+#[cfg(feature = "serde")]
+pub mod serde_table;
+mod stats;
 mod storage;
+mod symbol;
+#[cfg(feature = "std")]
+mod vocabulary;
+mod weak;
 
 /// An immutable and interned string.
 /// 
 /// Reading an `IString`'s contents is very fast, lock-free and wait-free.
 /// It can be shared and read from any number of threads.
 /// It scales linearly with the number of reading threads.
+/// Without the default `std` feature, the storage is a single copy in a `RefCell`,
+/// the crate must only be used by a single thread, and `IString` is neither `Send` nor `Sync`.
 /// 
 /// `IString` provides `Hash` and `Eq` implementations that run in O(1),
 /// perfect for an high performance `HashMap<IString, _>`
 /// 
+/// `IString`s are ordered lexicographically, like `str`.
+/// If you only need an arbitrary but stable order, see [`IString::cmp_by_key`] and [`ByKey`].
+/// 
 /// The tradeoff is that creating a new `IString` is comparatively slower :
 /// - Creating a new `IString` with a string that is already interned is fast and lock-free.
 /// - Creating a new `IString` with a string that isn't already interned is slower.
 ///   It acquires a global lock and waits for all readers to finish reading.
-#[derive(Eq, PartialEq, Ord, Hash)]
 pub struct IString {
-    pub(crate) key: IStringKey
+    pub(crate) key: IStringKey,
+    /// When true, this `IString` does not count as a reference to its string, because it is never freed.
+    pub(crate) is_pinned: bool,
+    /// The contents of the stored string, that live at least as long as this `IString`.
+    contents: NonNull<str>,
+}
+
+// Safety: the contents are an immutable `str`, that is not freed while the `IString` lives.
+// Note: without the `std` feature, the strings must not be sent to another thread.
+#[cfg(feature = "std")]
+unsafe impl Send for IString {}
+#[cfg(feature = "std")]
+unsafe impl Sync for IString {}
+
+impl IString {
+    #[inline]
+    fn from_retained(retained: RetainedKey) -> Self {
+        Self { key: retained.key, is_pinned: retained.is_pinned, contents: retained.contents }
+    }
 }
 
 // Indispensable traits impl : From, Drop, Deref
@@ -26,7 +86,7 @@ pub struct IString {
 impl From<String> for IString {
     /// Intern the given `String` by consuming it. Its allocation is reused.
     /// 
-    /// This operation runs in O(N) where N is the `string.len()`.
+    /// This operation runs in O(N log M) where N is the `string.len()` and M is the number of interned strings.
     /// If the string was already interned, this operation is lock-free.
     /// Otherwise, a global lock is acquired.
     /// 
@@ -39,18 +99,16 @@ impl From<String> for IString {
     /// ```
     #[inline]
     fn from(string: String) -> Self {
-        Self {
-            // could block
-            key: SHARED_STORAGE.insert_or_retain(string)
-        }
+        // could block
+        Self::from_retained(SHARED_INTERNER.insert_or_retain(string))
     }
 }
 
 impl From<&str> for IString {
     /// Intern the given `&str` by cloning its contents.
     /// 
-    /// This operation runs in O(N) where N is the `string.len()`.
-    /// If the string was already interned, this operation is lock-free.
+    /// This operation runs in O(N log M) where N is the `string.len()` and M is the number of interned strings.
+    /// If the string was already interned, this operation is lock-free, and its contents are not cloned.
     /// Otherwise, a global lock is acquired.
     /// 
     /// # Example
@@ -62,18 +120,22 @@ impl From<&str> for IString {
     /// ```
     #[inline]
     fn from(string: &str) -> Self {
-        Self {
-            // could block
-            key: SHARED_STORAGE.insert_or_retain(String::from(string))
+        if let Some(found) = SHARED_INTERNER.get_and_retain(string) {
+            return Self::from_retained(found);
         }
+        // could block
+        Self::from_retained(SHARED_INTERNER.insert(String::from(string)))
     }
 }
 
 impl Drop for IString {
     #[inline]
     fn drop(&mut self) {
-        THREAD_LOCAL_READER.with(|tl_reader| {
-            tl_reader.release(self);
+        if self.is_pinned {
+            return;
+        }
+        SHARED_INTERNER.with_reader(|tl_reader| {
+            tl_reader.release(self.key);
         });
     }
 }
@@ -99,9 +161,8 @@ impl Deref for IString {
     /// ```
     #[inline]
     fn deref(&self) -> &Self::Target {
-        THREAD_LOCAL_READER.with(|reader: &ThreadLocalReader| {
-            reader.read(self)
-        })
+        // Safety: self is retained and lives longer than the returned reference.
+        unsafe { self.contents.as_ref() }
     }
 }
 
@@ -119,25 +180,47 @@ impl AsRef<str> for IString {
     /// ```
     #[inline]
     fn as_ref(&self) -> &str {
-        THREAD_LOCAL_READER.with(|tl_reader: &ThreadLocalReader| {
-            tl_reader.read(self)
-        })
+        // Safety: self is retained and lives longer than the returned reference.
+        unsafe { self.contents.as_ref() }
     }
 }
 
-// Common traits impl that can't be derived : Clone, PartialOrd, Debug, Display, Default
+// Common traits impl that can't be derived : Clone, PartialEq, Hash, PartialOrd, Ord, Debug, Display, Default
 
 impl Clone for IString {
     /// Returns a copy of the `IString`.
     /// 
-    /// This operation runs in O(1) and is lock-free.
+    /// This operation runs in O(1) and is lock-free,
+    /// unless it is called by a thread-local destructor, after the thread-local storage of the crate was destroyed.
     #[inline]
     fn clone(&self) -> Self {
-        THREAD_LOCAL_READER.with(|reader: &ThreadLocalReader| {
-            reader.retain(self.key)
-        });
+        if !self.is_pinned {
+            SHARED_INTERNER.with_reader(|reader| {
+                reader.retain(self.key)
+            });
+        }
+
+        Self { key: self.key, is_pinned: self.is_pinned, contents: self.contents }
+    }
+}
+
+impl PartialEq for IString {
+    /// Returns `true` if both `IString`s have the same contents.
+    /// 
+    /// This operation runs in O(1).
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for IString {}
 
-        Self { key: self.key }
+impl core::hash::Hash for IString {
+    /// This operation runs in O(1).
+    #[inline]
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.key.hash(state)
     }
 }
 
@@ -163,22 +246,36 @@ impl PartialOrd for IString {
     }
     
     #[inline]
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.deref().partial_cmp(other.deref())
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IString {
+    /// Compares the contents of the `IString`s lexicographically, like `str`.
+    /// 
+    /// This operation runs in O(N) where N is the length of the shortest string,
+    /// or O(1) when both `IString`s are equal.
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        if self.key == other.key {
+            return core::cmp::Ordering::Equal;
+        }
+        self.deref().cmp(other.deref())
     }
 }
 
 impl Debug for IString {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_tuple("IString")
          .field(&self.deref())
          .finish()
     }
 }
 
-impl std::fmt::Display for IString {
+impl core::fmt::Display for IString {
     #[inline]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_str(self)
     }
 }
@@ -200,7 +297,7 @@ pub trait Intern {
 impl Intern for String {
     /// Intern the given `String` by consuming it. Its allocation is reused.
     /// 
-    /// This operation runs in O(N) where N is the `string.len()`.
+    /// This operation runs in O(N log M) where N is the `string.len()` and M is the number of interned strings.
     /// If the string was already interned, this operation is lock-free.
     /// Otherwise, a global lock is acquired.
     /// 
@@ -220,8 +317,8 @@ impl Intern for String {
 impl Intern for &str {
     /// Intern the given `&str` by cloning its contents.
     /// 
-    /// This operation runs in O(N) where N is the `string.len()`.
-    /// If the string was already interned, this operation is lock-free.
+    /// This operation runs in O(N log M) where N is the `string.len()` and M is the number of interned strings.
+    /// If the string was already interned, this operation is lock-free, and its contents are not cloned.
     /// Otherwise, a global lock is acquired.
     /// 
     /// # Example
@@ -237,6 +334,330 @@ impl Intern for &str {
     }
 }
 
+// Bulk interning
+
+impl IString {
+    /// Intern all the given strings, and returns them in the same order.
+    /// 
+    /// Unlike calling [`IString::from`] for each string, the global lock is acquired only once,
+    /// and the strings are published with a single wait for the readers.
+    /// The garbage is not collected meanwhile, the next insert collects it according to the [`GcPolicy`].
+    /// Use it to load a large vocabulary at once.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// use interned_string::IString;
+    /// 
+    /// let keywords = IString::intern_many(["fn", "let", "fn"]);
+    /// assert_eq!(keywords[0], keywords[2]);
+    /// ```
+    pub fn intern_many(strings: impl IntoIterator<Item = impl Into<String>>) -> Vec<IString> {
+        let strings = strings.into_iter().map(Into::into).collect();
+        SHARED_INTERNER.insert_or_retain_many(strings)
+            .into_iter()
+            .map(IString::from_retained)
+            .collect()
+    }
+}
+
+// Ordering by key
+
+impl IString {
+    /// Compares two `IString`s in O(1), in an arbitrary order that is stable for as long as
+    /// both strings are interned.
+    /// 
+    /// Unlike [`Ord::cmp`], this order is not lexicographic.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// use std::cmp::Ordering;
+    /// use interned_string::Intern;
+    /// 
+    /// let hello = "hello".intern();
+    /// assert_eq!(hello.cmp_by_key(&hello.clone()), Ordering::Equal);
+    /// assert_ne!(hello.cmp_by_key(&"world".intern()), Ordering::Equal);
+    /// ```
+    #[inline]
+    pub fn cmp_by_key(&self, other: &IString) -> core::cmp::Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// A wrapper around an `IString` that is ordered with [`IString::cmp_by_key`] instead of lexicographically.
+/// 
+/// Use it as the key of a `BTreeMap` or `BTreeSet` when you only need an arbitrary but stable order.
+/// 
+/// # Example
+/// 
+/// ```
+/// use std::collections::BTreeSet;
+/// use interned_string::{ByKey, Intern};
+/// 
+/// let mut set = BTreeSet::new();
+/// set.insert(ByKey("hello".intern()));
+/// assert!(set.contains(&ByKey("hello".intern())));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ByKey(pub IString);
+
+impl PartialOrd for ByKey {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ByKey {
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.0.cmp_by_key(&other.0)
+    }
+}
+
+impl Deref for ByKey {
+    type Target = IString;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// Lookup
+
+impl IString {
+    /// Returns the `IString` with the given contents if it is already interned.
+    ///
+    /// Unlike [`IString::from`], this never interns a new string, so it never acquires the global lock.
+    /// Use it to check untrusted input against a known vocabulary without growing it.
+    ///
+    /// This operation runs in O(N log M) where N is the `string.len()` and M is the number of interned strings,
+    /// and is lock-free.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use interned_string::{IString, Intern};
+    ///
+    /// let get = "GET".intern();
+    /// assert_eq!(IString::get("GET"), Some(get));
+    /// assert_eq!(IString::get("YEET"), None);
+    /// ```
+    #[inline]
+    pub fn get(string: &str) -> Option<IString> {
+        SHARED_INTERNER.get_and_retain(string).map(IString::from_retained)
+    }
+
+    /// Returns `true` if a string with the given contents is interned.
+    ///
+    /// This operation runs in O(N log M) where N is the `string.len()` and M is the number of interned strings,
+    /// and is lock-free.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use interned_string::{IString, Intern};
+    ///
+    /// let get = "GET".intern();
+    /// assert!(IString::contains("GET"));
+    /// assert!(!IString::contains("YEET"));
+    /// ```
+    #[inline]
+    pub fn contains(string: &str) -> bool {
+        SHARED_INTERNER.contains(string)
+    }
+
+    /// Returns all the interned strings that start with the given prefix, in lexicographic order.
+    /// 
+    /// This operation is lock-free, and only visits the strings that start with the prefix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use interned_string::{IString, Intern};
+    ///
+    /// let name = "user.name".intern();
+    /// let id = "user.id".intern();
+    /// assert_eq!(IString::with_prefix("user."), [id, name]);
+    /// ```
+    pub fn with_prefix(prefix: &str) -> Vec<IString> {
+        SHARED_INTERNER.with_reader(|tl_reader| tl_reader.get_and_retain_with_prefix(prefix))
+            .into_iter()
+            .map(IString::from_retained)
+            .collect()
+    }
+
+    /// Returns the longest interned string that is a prefix of the given string, if there is one.
+    /// 
+    /// This operation is lock-free.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use interned_string::{IString, Intern};
+    ///
+    /// let api = "/api".intern();
+    /// let users = "/api/users".intern();
+    /// assert_eq!(IString::longest_interned_prefix_of("/api/users/42"), Some(users));
+    /// assert_eq!(IString::longest_interned_prefix_of("/api/groups"), Some(api));
+    /// ```
+    pub fn longest_interned_prefix_of(string: &str) -> Option<IString> {
+        SHARED_INTERNER.with_reader(|tl_reader| tl_reader.get_and_retain_longest_prefix_of(string))
+            .map(IString::from_retained)
+    }
+
+    /// Returns every interned string with its reference count, in lexicographic order.
+    /// 
+    /// All the strings are retained from the same consistent snapshot of the storage,
+    /// so they are kept alive for as long as the iterator or the returned `IString`s live.
+    /// The reference counts are the ones of the last time the storage was updated,
+    /// they don't count the returned `IString`s, nor the `IString`s created after a string was pinned.
+    /// Strings that are no longer used but not freed yet are reported with a reference count of 0.
+    /// 
+    /// This operation runs in O(M) where M is the number of interned strings, and is lock-free.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use interned_string::{IString, Intern};
+    ///
+    /// let hello = "hello".intern();
+    /// let vocabulary: Vec<IString> = IString::interned_strings().map(|(string, _)| string).collect();
+    /// assert!(vocabulary.contains(&hello));
+    /// ```
+    pub fn interned_strings() -> impl Iterator<Item = (IString, usize)> {
+        SHARED_INTERNER.with_reader(|tl_reader| tl_reader.get_and_retain_all())
+            .into_iter()
+            .map(|(found, reference_count)| (IString::from_retained(found), reference_count))
+    }
+}
+
+// Pinning
+
+impl IString {
+    /// Intern the given static string as a permanent string, that is never freed.
+    /// 
+    /// Cloning and dropping an `IString` of a permanent string is cheaper, because it is not reference counted.
+    /// Use it for the strings that your program uses all the time, like keywords.
+    /// 
+    /// If the string was already interned and pinned, this operation is lock-free.
+    /// Otherwise, a global lock is acquired.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// use interned_string::{IString, Intern};
+    /// 
+    /// let content_type = IString::from_static("content-type");
+    /// assert_eq!(content_type, "content-type".intern());
+    /// ```
+    pub fn from_static(string: &'static str) -> IString {
+        let mut istring = IString::from(string);
+        istring.pin();
+        istring
+    }
+
+    /// Marks the string as permanent: it will never be freed, even if it is no longer used.
+    /// 
+    /// This `IString`, its future clones, and the `IString`s created afterwards with the same contents
+    /// are no longer reference counted.
+    /// 
+    /// If the string is not pinned yet, a global lock is acquired.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// use interned_string::Intern;
+    /// 
+    /// let mut keyword = "return".intern();
+    /// keyword.pin();
+    /// ```
+    pub fn pin(&mut self) {
+        if !self.is_pinned {
+            SHARED_INTERNER.pin(self.key);
+            // the reference count of a pinned string no longer matters,
+            // so this IString keeps its retain forever
+            self.is_pinned = true;
+        }
+    }
+}
+
+/// Interns a string literal once per call site, and returns a clone of its permanent `IString` afterwards.
+///
+/// The first call interns the literal with [`IString::from_static`], the next ones only clone it,
+/// which is not reference counted. Use it for the literals of your hot paths.
+///
+/// # Example
+///
+/// ```
+/// use interned_string::{istr, IString};
+///
+/// fn is_content_type(header: &IString) -> bool {
+///     // O(1), the literal is only interned by the first call
+///     *header == istr!("content-type")
+/// }
+///
+/// assert!(is_content_type(&IString::from("content-type")));
+/// ```
+#[macro_export]
+macro_rules! istr {
+    ($string:literal) => {{
+        static ISTRING: $crate::__private::StaticIString = $crate::__private::StaticIString::new();
+        ISTRING.get($string)
+    }};
+}
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "std")]
+    use once_cell::sync::OnceCell;
+
+    #[cfg(not(feature = "std"))]
+    use crate::storage::local::SingleThreaded;
+    use crate::IString;
+
+    /// The `IString` of an [`istr!`](crate::istr) call site.
+    #[cfg(feature = "std")]
+    pub struct StaticIString {
+        istring: OnceCell<IString>,
+    }
+
+    #[cfg(feature = "std")]
+    impl StaticIString {
+        #[allow(clippy::new_without_default)]
+        pub const fn new() -> Self {
+            StaticIString { istring: OnceCell::new() }
+        }
+
+        #[inline]
+        pub fn get(&self, string: &'static str) -> IString {
+            self.istring.get_or_init(|| IString::from_static(string)).clone()
+        }
+    }
+
+    /// The `IString` of an [`istr!`](crate::istr) call site.
+    #[cfg(not(feature = "std"))]
+    pub struct StaticIString {
+        istring: SingleThreaded<Option<IString>>,
+    }
+
+    #[cfg(not(feature = "std"))]
+    impl StaticIString {
+        #[allow(clippy::new_without_default)]
+        pub const fn new() -> Self {
+            StaticIString { istring: SingleThreaded::new(None) }
+        }
+
+        #[inline]
+        pub fn get(&self, string: &'static str) -> IString {
+            let mut istring = self.istring.borrow_mut();
+            istring.get_or_insert_with(|| IString::from_static(string)).clone()
+        }
+    }
+}
+
 // Garbage collection
 
 impl IString {
@@ -249,24 +670,61 @@ impl IString {
     /// 
     /// Using this function is optional. Memory is always eventually freed.
     pub fn collect_garbage_now() {
-        SHARED_STORAGE.writer.lock().unwrap().collect_garbage();
+        SHARED_INTERNER.collect_garbage_now();
+    }
+
+    /// Returns the current garbage collection policy.
+    pub fn gc_policy() -> GcPolicy {
+        SHARED_INTERNER.gc_policy()
+    }
+
+    /// Changes when the interned strings that are no longer used are freed.
+    /// 
+    /// By default, they are freed every time a new string is interned. See [`GcPolicy`].
+    pub fn set_gc_policy(gc_policy: GcPolicy) {
+        SHARED_INTERNER.set_gc_policy(gc_policy);
+    }
+
+    /// Starts a thread that frees the interned strings that are no longer used, every `interval`.
+    /// 
+    /// This is useful when your program stops interning new strings for a while,
+    /// for example with [`GcPolicy::Manual`], so that the memory of unused strings is still reclaimed.
+    /// The strings that [`GcPolicy::KeepRecentlyUnused`] asks to keep are not freed.
+    /// 
+    /// The thread stops when the returned [`BackgroundCollector`] is dropped.
+    #[cfg(feature = "std")]
+    pub fn start_background_collector(interval: Duration) -> BackgroundCollector {
+        BackgroundCollector::spawn(interval, || SHARED_INTERNER.collect_garbage_in_background())
+    }
+}
+
+// Statistics
+
+impl IString {
+    /// Returns a snapshot of the state of the interned strings storage.
+    /// 
+    /// This acquires the same lock as interning a new string, but doesn't wait for readers.
+    pub fn stats() -> Stats {
+        SHARED_INTERNER.stats()
     }
 }
 
 #[cfg(feature = "serde")]
 mod feature_serde {
+    use alloc::string::String;
     use serde::{de::Visitor, Deserialize, Serialize};
     use crate::IString;
 
     impl Serialize for IString {
         fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-            serializer.serialize_str(std::ops::Deref::deref(&self))
+            serializer.serialize_str(core::ops::Deref::deref(&self))
         }
     }
     
     impl<'de> Deserialize<'de> for IString {
         fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-            deserializer.deserialize_string(IStringVisitor)
+            // Note: not `deserialize_string`, that lets formats like bincode allocate a `String` before the lookup
+            deserializer.deserialize_str(IStringVisitor)
         }
     }
     
@@ -275,7 +733,7 @@ mod feature_serde {
     impl<'de> Visitor<'de> for IStringVisitor {
         type Value = IString;
     
-        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
             formatter.write_str("a string")
         }
     
@@ -285,7 +743,12 @@ mod feature_serde {
         }
     
         fn visit_str<E: serde::de::Error>(self, slice: &str) -> Result<Self::Value, E> {
-            // less performant, will allocate
+            // only allocates if the string is not interned yet
+            Ok(IString::from(slice))
+        }
+
+        fn visit_borrowed_str<E: serde::de::Error>(self, slice: &'de str) -> Result<Self::Value, E> {
+            // only allocates if the string is not interned yet
             Ok(IString::from(slice))
         }
     }
@@ -293,13 +756,13 @@ mod feature_serde {
 
 // tests
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use std::{ops::Deref, sync::Mutex};
-    use radix_trie::TrieCommon;
+    use std::{cell::RefCell, ops::Deref, sync::Mutex};
 
     use super::*;
-    use crate::storage::SHARED_STORAGE;
+    #[cfg(feature = "serde")]
+    use crate::allocation_counter::total_allocated_bytes_in_this_thread;
 
     #[test]
     fn it_creates_and_removes_1_string() {
@@ -397,6 +860,410 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_looks_up_strings_without_interning_them() {
+        with_exclusive_use_of_shared_storage(|| {
+            let my_istring1 = IString::from("hello");
+
+            assert!(IString::contains("hello"));
+            assert!(!IString::contains("world"));
+
+            let my_istring2 = IString::get("hello").unwrap();
+            assert!(my_istring2.key == my_istring1.key);
+            assert!(IString::get("world").is_none());
+
+            assert_string_count_in_storage(1);
+            assert_string_is_not_stored("world");
+
+            drop(my_istring1);
+            drop(my_istring2);
+            IString::collect_garbage_now();
+
+            assert!(!IString::contains("hello"));
+            assert!(IString::get("hello").is_none());
+            assert_string_count_in_storage(0);
+        });
+    }
+
+    #[test]
+    fn it_never_frees_pinned_strings() {
+        with_exclusive_use_of_shared_storage(|| {
+            let my_istring1 = IString::from_static("hello");
+            assert!(my_istring1.is_pinned);
+            let my_istring2 = IString::from("world");
+            let my_istring3 = my_istring1.clone();
+            assert!(my_istring3.is_pinned);
+            drop(my_istring1);
+            drop(my_istring2);
+            drop(my_istring3);
+            IString::collect_garbage_now();
+
+            assert_string_count_in_storage(1);
+            assert_string_is_still_stored("hello");
+            assert_string_is_not_stored("world");
+
+            // the IStrings created afterwards are pinned as well
+            let my_istring4 = IString::from("hello");
+            assert!(my_istring4.is_pinned);
+            assert!(IString::get("hello").unwrap().is_pinned);
+
+            let mut my_istring5 = IString::from("world");
+            assert!(!my_istring5.is_pinned);
+            let my_istring6 = my_istring5.clone();
+            my_istring5.pin();
+            assert!(my_istring5.is_pinned);
+            // an IString created before pinning is still reference counted
+            assert!(!my_istring6.is_pinned);
+            drop(my_istring5);
+            drop(my_istring6);
+            IString::collect_garbage_now();
+
+            assert_string_count_in_storage(2);
+            assert_string_is_still_stored("world");
+            assert!(IString::from("world").is_pinned);
+        });
+    }
+
+    #[test]
+    fn it_follows_the_gc_policy() {
+        with_exclusive_use_of_shared_storage(|| {
+            IString::set_gc_policy(GcPolicy::Manual);
+            assert_eq!(IString::gc_policy(), GcPolicy::Manual);
+
+            drop(IString::from("hello"));
+            let my_istring = IString::from("world");
+            assert_string_count_in_storage(2);
+            assert_string_is_still_stored("hello");
+
+            IString::collect_garbage_now();
+            assert_string_count_in_storage(1);
+            assert_string_is_stored_with_key("world", my_istring.key);
+        });
+    }
+
+    #[test]
+    fn it_collects_garbage_in_the_background() {
+        with_exclusive_use_of_shared_storage(|| {
+            IString::set_gc_policy(GcPolicy::Manual);
+            let collector = IString::start_background_collector(Duration::from_millis(1));
+
+            let my_istring = IString::from("hello");
+            drop(IString::from("world"));
+
+            let deadline = std::time::Instant::now() + Duration::from_secs(10);
+            while IString::contains("world") {
+                assert!(std::time::Instant::now() < deadline, "the background collector did not run");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            collector.stop();
+
+            assert_string_count_in_storage(1);
+            assert_string_is_stored_with_key("hello", my_istring.key);
+        });
+    }
+
+    #[test]
+    fn it_reads_the_stored_contents() {
+        with_exclusive_use_of_shared_storage(|| {
+            let inserted = IString::from("hello");
+            let found = IString::from("hello");
+            let cloned = found.clone();
+
+            let guard = SHARED_INTERNER.storage.read_handle.lock().unwrap();
+            let read_handle = guard.enter().unwrap();
+            let stored = read_handle.map.get(&inserted.key).unwrap().inner.deref();
+            for my_istring in [&inserted, &found, &cloned] {
+                assert!(std::ptr::eq(my_istring.deref(), stored));
+                assert!(std::ptr::eq(my_istring.as_ref(), stored));
+            }
+        });
+    }
+
+    #[test]
+    fn it_interns_literals_once_per_call_site() {
+        with_exclusive_use_of_shared_storage(|| {
+            let content_type = || istr!("content-type");
+            let my_istring1 = content_type();
+            assert!(my_istring1.is_pinned);
+            let my_istring2 = content_type();
+            assert_eq!(my_istring1.contents, my_istring2.contents);
+            assert_eq!(my_istring1, IString::from("content-type"));
+            assert_eq!(my_istring1, istr!("content-type"));
+            drop(my_istring1);
+            drop(my_istring2);
+            IString::collect_garbage_now();
+
+            assert_string_is_still_stored("content-type");
+        });
+    }
+
+    #[test]
+    fn it_interns_many_strings_at_once() {
+        with_exclusive_use_of_shared_storage(|| {
+            let my_istrings = IString::intern_many(vec!["hello".to_string(), "world".to_string(), "hello".to_string()]);
+            assert_eq!(my_istrings[0], my_istrings[2]);
+            assert_string_count_in_storage(2);
+            assert_string_is_stored_with_key("hello", my_istrings[0].key);
+            assert_string_is_stored_with_key("world", my_istrings[1].key);
+        });
+    }
+
+    #[test]
+    fn it_coalesces_clones_and_drops() {
+        with_exclusive_use_of_shared_storage(|| {
+            let my_istring = IString::from("hello");
+            IString::collect_garbage_now();
+            assert_eq!(IString::stats().pending_ops, 0);
+
+            for _ in 0..1000 {
+                drop(my_istring.clone());
+            }
+            // only the first clone was sent, the other ones canceled the previous drop
+            assert_eq!(IString::stats().pending_ops, 1);
+
+            // the drops of a thread are sent when it exits
+            let my_clone = my_istring.clone();
+            std::thread::spawn(move || drop(my_clone)).join().unwrap();
+            drop(my_istring);
+            IString::collect_garbage_now();
+            assert_string_count_in_storage(0);
+        });
+    }
+
+    #[test]
+    fn it_frees_the_strings_dropped_by_idle_threads() {
+        with_exclusive_use_of_shared_storage(|| {
+            let (dropped_sender, dropped_receiver) = std::sync::mpsc::channel();
+            let (exit_sender, exit_receiver) = std::sync::mpsc::channel::<()>();
+            let idle_thread = std::thread::spawn(move || {
+                drop(IString::from("idle"));
+                dropped_sender.send(()).unwrap();
+                // blocks without using the strings anymore, like a worker waiting for jobs
+                exit_receiver.recv().unwrap();
+            });
+
+            dropped_receiver.recv().unwrap();
+            IString::collect_garbage_now();
+            assert!(!IString::contains("idle"));
+
+            exit_sender.send(()).unwrap();
+            idle_thread.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn it_reads_held_strings_while_other_threads_insert_and_collect() {
+        with_exclusive_use_of_shared_storage(|| {
+            std::thread::scope(|scope| {
+                for thread in 0..8 {
+                    scope.spawn(move || {
+                        // holds the strings it found across the inserts and collections of the other threads
+                        let mut held = std::collections::VecDeque::new();
+                        for index in 0..20_000 {
+                            // found, dropped and freed over and over, so that their keys are issued again
+                            let contents = format!("churn {}", (index * 7 + thread) % 64);
+                            held.push_back((IString::from(contents.as_str()), contents));
+                            if held.len() > 16 {
+                                held.pop_front();
+                            }
+                            for (my_istring, contents) in &held {
+                                assert_eq!(my_istring.deref(), contents);
+                            }
+                            if index % 64 == thread {
+                                IString::collect_garbage_now();
+                            }
+                        }
+                    });
+                }
+            });
+
+            IString::collect_garbage_now();
+            assert_eq!(IString::with_prefix("churn"), []);
+        });
+    }
+
+    #[test]
+    fn it_is_stored_in_thread_locals() {
+        thread_local! {
+            static MY_ISTRINGS: RefCell<Vec<IString>> = const { RefCell::new(Vec::new()) };
+        }
+
+        with_exclusive_use_of_shared_storage(|| {
+            std::thread::spawn(|| {
+                // initialized before the reader of this thread, so it is destroyed after it
+                MY_ISTRINGS.with(|_| ());
+                let my_istring = IString::from("hello");
+                MY_ISTRINGS.with(|my_istrings| my_istrings.borrow_mut().extend([my_istring.clone(), my_istring]));
+            }).join().unwrap();
+
+            IString::collect_garbage_now();
+            assert_string_count_in_storage(0);
+        });
+    }
+
+    #[test]
+    fn it_is_used_by_thread_local_destructors() {
+        static CLONED_CONTENTS: Mutex<Option<String>> = Mutex::new(None);
+
+        struct CloneOnDrop(IString);
+
+        impl Drop for CloneOnDrop {
+            fn drop(&mut self) {
+                let my_clone = self.0.clone();
+                *CLONED_CONTENTS.lock().unwrap() = Some(my_clone.to_string());
+            }
+        }
+
+        thread_local! {
+            static MY_CLONE_ON_DROP: RefCell<Option<CloneOnDrop>> = const { RefCell::new(None) };
+        }
+
+        with_exclusive_use_of_shared_storage(|| {
+            std::thread::spawn(|| {
+                // initialized before the reader of this thread, so it is destroyed after it
+                MY_CLONE_ON_DROP.with(|_| ());
+                let my_istring = IString::from("hello");
+                MY_CLONE_ON_DROP.with(|my_clone_on_drop| *my_clone_on_drop.borrow_mut() = Some(CloneOnDrop(my_istring)));
+            }).join().unwrap();
+
+            assert_eq!(CLONED_CONTENTS.lock().unwrap().as_deref(), Some("hello"));
+            IString::collect_garbage_now();
+            assert_string_count_in_storage(0);
+        });
+    }
+
+    #[test]
+    fn it_reuses_the_keys_of_freed_strings() {
+        with_exclusive_use_of_shared_storage(|| {
+            // only the keys 0 and 1 can be issued
+            SHARED_INTERNER.storage.writer.lock().unwrap().reset_key_space(2);
+
+            let my_istring1 = IString::from("hello");
+            let my_istring2 = IString::from("world");
+            assert_eq!(my_istring1.key, 0);
+            assert_eq!(my_istring2.key, 1);
+
+            drop(my_istring1);
+
+            // the key space is exhausted, so the key of "hello" is reused
+            let my_istring3 = IString::from("howdy");
+            assert_eq!(my_istring3.key, 0);
+            assert!(my_istring3.deref() == "howdy");
+            assert!(my_istring2.deref() == "world");
+
+            assert_string_count_in_storage(2);
+            assert_string_is_stored_with_key("howdy", 0);
+            assert_string_is_stored_with_key("world", 1);
+            assert_string_is_not_stored("hello");
+        });
+    }
+
+    #[test]
+    fn it_bounds_the_key_space_by_the_number_of_live_strings() {
+        with_exclusive_use_of_shared_storage(|| {
+            SHARED_INTERNER.storage.writer.lock().unwrap().reset_key_space(4);
+
+            let mut live_istrings = std::collections::VecDeque::new();
+            for i in 0..1000 {
+                let my_istring = format!("string #{i}").intern();
+                assert!(my_istring.key < 4);
+                live_istrings.push_back(my_istring);
+                if live_istrings.len() == 3 {
+                    live_istrings.pop_front();
+                }
+            }
+
+            for my_istring in live_istrings.iter() {
+                assert_string_is_stored_with_key(my_istring, my_istring.key);
+            }
+        });
+    }
+
+    #[test]
+    fn it_iterates_over_interned_strings() {
+        with_exclusive_use_of_shared_storage(|| {
+            let my_istring = IString::from("hello");
+            let _my_istring_clone = my_istring.clone();
+            let pinned = IString::from_static("world");
+            let _pinned_clone = pinned.clone();
+            // apply the pending reference count updates
+            IString::collect_garbage_now();
+
+            let interned: Vec<(IString, usize)> = IString::interned_strings().collect();
+            assert_eq!(interned, [(my_istring.clone(), 2), (pinned.clone(), 1)]);
+
+            // the strings are retained, and the storage can be updated while iterating
+            drop(my_istring);
+            drop(_my_istring_clone);
+            for (string, _) in IString::interned_strings() {
+                IString::collect_garbage_now();
+                let mut upper = IString::from(string.to_uppercase());
+                upper.pin();
+                assert_string_is_still_stored(&string);
+            }
+            drop(interned);
+            IString::collect_garbage_now();
+            let interned: Vec<String> = IString::interned_strings().map(|(string, _)| string.to_string()).collect();
+            assert_eq!(interned, ["HELLO", "WORLD", "world"]);
+        });
+    }
+
+    #[test]
+    fn it_finds_strings_by_prefix() {
+        with_exclusive_use_of_shared_storage(|| {
+            let strings = ["user.name", "user", "user.id", "group.id"].map(IString::from);
+
+            assert_eq!(IString::with_prefix("user."), [strings[2].clone(), strings[0].clone()]);
+            assert_eq!(IString::with_prefix("admin."), []);
+            assert_eq!(IString::longest_interned_prefix_of("user.email"), Some(strings[1].clone()));
+            assert_eq!(IString::longest_interned_prefix_of("group"), None);
+        });
+    }
+
+    #[test]
+    fn it_orders_lexicographically() {
+        with_exclusive_use_of_shared_storage(|| {
+            // interned in a different order than the lexicographic order
+            let strings = ["b", "c", "a", "ab", ""].map(IString::from);
+
+            for left in strings.iter() {
+                for right in strings.iter() {
+                    let expected = left.deref().cmp(right.deref());
+                    assert_eq!(left.cmp(right), expected);
+                    assert_eq!(left.partial_cmp(right), Some(expected));
+                    assert_eq!(left < right, expected.is_lt());
+                    assert_eq!(left <= right, expected.is_le());
+                    assert_eq!(left > right, expected.is_gt());
+                    assert_eq!(left >= right, expected.is_ge());
+                    assert_eq!(left == right, expected.is_eq());
+                }
+            }
+
+            let mut sorted = strings.to_vec();
+            sorted.sort();
+            assert_eq!(sorted.iter().map(|s| s.deref()).collect::<Vec<_>>(), ["", "a", "ab", "b", "c"]);
+
+            let map: std::collections::BTreeMap<IString, usize> = strings.iter().cloned().zip(0..).collect();
+            assert_eq!(map.keys().map(|s| s.deref()).collect::<Vec<_>>(), ["", "a", "ab", "b", "c"]);
+        });
+    }
+
+    #[test]
+    fn it_orders_by_key() {
+        with_exclusive_use_of_shared_storage(|| {
+            let strings = ["b", "c", "a"].map(IString::from);
+
+            for left in strings.iter() {
+                for right in strings.iter() {
+                    let expected = left.key.cmp(&right.key);
+                    assert_eq!(left.cmp_by_key(right), expected);
+                    assert_eq!(ByKey(left.clone()).cmp(&ByKey(right.clone())), expected);
+                    assert_eq!(ByKey(left.clone()) == ByKey(right.clone()), expected.is_eq());
+                }
+            }
+        });
+    }
+
     #[test]
     fn it_is_send() {
         fn assert_send<T: Send>() {}
@@ -426,6 +1293,33 @@ mod tests {
         });
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_deserializes_interned_strings_without_cloning_them() {
+        with_exclusive_use_of_shared_storage(|| {
+            let pasta = "pasta".repeat(200);
+            let serialized = format!("[\"{pasta}\",\"{pasta}\"]");
+            let my_istring = IString::from(pasta.as_str());
+
+            let allocated_before = total_allocated_bytes_in_this_thread();
+            let deserialized: Vec<IString> = serde_json::from_str(&serialized).unwrap();
+            let allocated = total_allocated_bytes_in_this_thread() - allocated_before;
+
+            assert_eq!(deserialized, [my_istring.clone(), my_istring.clone()]);
+            // what remains are the vector and the Retain operations
+            assert!(allocated < pasta.len(), "{allocated} bytes were allocated to deserialize {} bytes", serialized.len());
+
+            // bincode allocates a `String` if it's asked for one
+            let serialized = bincode::serialize(&vec![pasta.as_str(); 2]).unwrap();
+            let allocated_before = total_allocated_bytes_in_this_thread();
+            let deserialized: Vec<IString> = bincode::deserialize(&serialized).unwrap();
+            let allocated = total_allocated_bytes_in_this_thread() - allocated_before;
+
+            assert_eq!(deserialized, [my_istring.clone(), my_istring]);
+            assert!(allocated < pasta.len(), "{allocated} bytes were allocated to deserialize {} bytes", serialized.len());
+        });
+    }
+
     #[cfg(feature = "serde")]
     #[test]
     fn it_deserializes() {
@@ -446,47 +1340,48 @@ mod tests {
     }
 
     fn assert_string_count_in_storage(count: usize) {
-        let guard = SHARED_STORAGE.read_handle.lock().unwrap();
+        let guard = SHARED_INTERNER.storage.read_handle.lock().unwrap();
         let read_handle = guard.enter().unwrap();
         assert_eq!(read_handle.map.len(), count);
-        assert_eq!(read_handle.trie.len(), count);
+        assert_eq!(read_handle.index.len(), count);
     }
 
     fn assert_string_is_still_stored(string: &str) {
-        let guard = SHARED_STORAGE.read_handle.lock().unwrap();
+        let guard = SHARED_INTERNER.storage.read_handle.lock().unwrap();
         let read_handle = guard.enter().unwrap();
-        let key = read_handle.trie.get(&string.into());
+        let key = read_handle.index.get(string);
         if let Some(key) = key {
-            assert!(read_handle.map.get(&key).unwrap().inner.deref() == string);
+            assert!(read_handle.map.get(key).unwrap().inner.deref() == string);
         } else {
-            assert!(false, "the string is not in the trie");
+            panic!("the string is not in the index");
         }
     }
 
     fn assert_string_is_stored_with_key(string: &str, key: u32) {
-        let guard = SHARED_STORAGE.read_handle.lock().unwrap();
+        let guard = SHARED_INTERNER.storage.read_handle.lock().unwrap();
         let read_handle = guard.enter().unwrap();
         assert!(read_handle.map.get(&key).unwrap().inner.deref() == string);
-        assert_eq!(read_handle.trie.get(&string.into()), Some(&key));
+        assert_eq!(read_handle.index.get(string), Some(&key));
     }
 
     fn assert_string_is_not_stored(string: &str) {
-        let guard = SHARED_STORAGE.read_handle.lock().unwrap();
+        let guard = SHARED_INTERNER.storage.read_handle.lock().unwrap();
         let read_handle = guard.enter().unwrap();
-        assert_eq!(read_handle.trie.get(&string.into()), None);
+        assert_eq!(read_handle.index.get(string), None);
     }
 
     static SHARED_STORAGE_MUTEX: Mutex<()> = Mutex::new(());
 
-    fn with_exclusive_use_of_shared_storage(closure: fn()) {
+    pub(crate) fn with_exclusive_use_of_shared_storage(closure: fn()) {
         let guard = SHARED_STORAGE_MUTEX.lock().expect("test lock is not poisoned");
         closure();
 
         // reset the writer for the next test
-        let mut writer = SHARED_STORAGE.writer.lock().unwrap();
-        writer.drain_channel_ops();
-        writer.write_handle.append(storage::StringStorageOp::DropUnusedStrings);
-        writer.write_handle.publish();
+        let mut writer = SHARED_INTERNER.storage.writer.lock().unwrap();
+        writer.unpin_all_strings();
+        writer.collect_garbage();
+        writer.set_gc_policy(GcPolicy::default());
+        writer.reset_key_space(IStringKey::MAX);
         drop(writer);
         drop(guard);
     }
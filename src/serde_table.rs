@@ -0,0 +1,890 @@
+//! Serde adapters for values that contain many [`IString`](crate::IString)s.
+
+/// Serializes each distinct string of a value once, in a table of strings,
+/// and every occurrence as its index in this table.
+///
+/// Collections of records that repeat the same strings shrink a lot, and deserializing them
+/// interns each distinct string once. Use it with `#[serde(with = "interned_string::serde_table::with_string_table")]`
+/// on a field that contains `IString`s, for example a `Vec` of records.
+///
+/// The value is serialized as a struct with a `strings` field and a `value` field,
+/// so it works with self-describing formats like JSON and with compact formats like bincode.
+/// The value is visited twice when serialized: once to collect its strings, then to write it.
+///
+/// The other strings of the value, like `String`s, are replaced by indexes too: deserializing an index gives the
+/// string of the table, so an `IString` finds it without allocating, and a `String` copies it.
+/// The table is only used by the serializers and deserializers of the adapter: an `IString` given to an unrelated
+/// serializer in the meantime, for example by a `serialize_with` function, is serialized as a plain string.
+/// The strings that serde buffers while deserializing, those in untagged or internally tagged enums
+/// and in flattened fields, can't be read back from their indexes.
+///
+/// # Example
+///
+/// ```
+/// use interned_string::IString;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// struct Order {
+///     dish: IString,
+///     quantity: u32,
+/// }
+///
+/// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// struct Orders {
+///     #[serde(with = "interned_string::serde_table::with_string_table")]
+///     orders: Vec<Order>,
+/// }
+///
+/// let orders = Orders {
+///     orders: vec![
+///         Order { dish: IString::from("pasta"), quantity: 1 },
+///         Order { dish: IString::from("pizza"), quantity: 2 },
+///         Order { dish: IString::from("pasta"), quantity: 3 },
+///     ],
+/// };
+///
+/// let json = serde_json::to_string(&orders).unwrap();
+/// assert_eq!(json, r#"{"orders":{"strings":["pasta","pizza"],"value":[{"dish":0,"quantity":1},{"dish":1,"quantity":2},{"dish":0,"quantity":3}]}}"#);
+/// assert_eq!(serde_json::from_str::<Orders>(&json).unwrap(), orders);
+/// ```
+pub mod with_string_table {
+    use alloc::{boxed::Box, string::{String, ToString}, vec, vec::Vec};
+    use core::{cell::RefCell, fmt, marker::PhantomData};
+    #[cfg(feature = "std")]
+    use std::collections::HashMap as StringIndexes;
+    #[cfg(not(feature = "std"))]
+    use alloc::collections::BTreeMap as StringIndexes;
+
+    use ::serde::{
+        de::{self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor},
+        ser::{self, Error as _, Serialize, SerializeStruct, Serializer},
+    };
+
+    use crate::IString;
+
+    const FIELDS: &[&str] = &["strings", "value"];
+
+    /// Serializes the value with a table of its distinct `IString`s, see the [module docs](self).
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized + Serialize,
+        S: Serializer,
+    {
+        // the first pass only collects the strings, so that the table can be written before the value
+        let table = RefCell::new(SerializedStrings::default());
+        value.serialize(StringCollector { table: &table }).map_err(S::Error::custom)?;
+        let table = table.into_inner();
+
+        let mut state = serializer.serialize_struct("StringTable", FIELDS.len())?;
+        state.serialize_field("strings", &table.strings())?;
+        state.serialize_field("value", &WithTable { value, table: &table })?;
+        state.end()
+    }
+
+    /// Deserializes a value serialized by [`serialize`], interning each string of its table once.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("StringTable", FIELDS, StringTableVisitor(PhantomData))
+    }
+
+    #[derive(Default)]
+    struct SerializedStrings {
+        indexes: StringIndexes<Box<str>, u32>,
+    }
+
+    impl SerializedStrings {
+        fn insert(&mut self, string: &str) -> Result<(), CollectError> {
+            if !self.indexes.contains_key(string) {
+                let index = u32::try_from(self.indexes.len())
+                    .map_err(|_| CollectError::custom("the value contains more than u32::MAX distinct strings"))?;
+                self.indexes.insert(Box::from(string), index);
+            }
+            Ok(())
+        }
+
+        fn index_of<E: ser::Error>(&self, string: &str) -> Result<u32, E> {
+            self.indexes
+                .get(string)
+                .copied()
+                .ok_or_else(|| E::custom("the value contained different strings when it was serialized twice"))
+        }
+
+        /// The strings in the order of their indexes.
+        fn strings(&self) -> Vec<&str> {
+            let mut strings = vec![""; self.indexes.len()];
+            for (string, &index) in &self.indexes {
+                strings[index as usize] = string;
+            }
+            strings
+        }
+    }
+
+    struct StringTableVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for StringTableVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a string table and a value")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+            let strings: Vec<String> = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            let strings = IString::intern_many(strings);
+            seq.next_element_seed(WithTable { value: PhantomData, table: strings.as_slice() })?
+                .ok_or_else(|| de::Error::invalid_length(1, &self))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+            let mut strings = None;
+            let mut value = None;
+            while let Some(field) = map.next_key()? {
+                match field {
+                    Field::Strings if strings.is_some() => return Err(de::Error::duplicate_field("strings")),
+                    Field::Strings => strings = Some(IString::intern_many(map.next_value::<Vec<String>>()?)),
+                    Field::Value if value.is_some() => return Err(de::Error::duplicate_field("value")),
+                    Field::Value => {
+                        // the indexes of the value can't be resolved without the table
+                        let Some(strings) = &strings else {
+                            return Err(de::Error::custom("the strings must come before the value"));
+                        };
+                        value = Some(map.next_value_seed(WithTable { value: PhantomData, table: strings.as_slice() })?);
+                    }
+                }
+            }
+            value.ok_or_else(|| de::Error::missing_field("value"))
+        }
+    }
+
+    enum Field {
+        Strings,
+        Value,
+    }
+
+    impl<'de> Deserialize<'de> for Field {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_identifier(FieldVisitor)
+        }
+    }
+
+    struct FieldVisitor;
+
+    impl<'de> Visitor<'de> for FieldVisitor {
+        type Value = Field;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("`strings` or `value`")
+        }
+
+        fn visit_u64<E: de::Error>(self, index: u64) -> Result<Field, E> {
+            match index {
+                0 => Ok(Field::Strings),
+                1 => Ok(Field::Value),
+                _ => Err(E::invalid_value(de::Unexpected::Unsigned(index), &self)),
+            }
+        }
+
+        fn visit_str<E: de::Error>(self, name: &str) -> Result<Field, E> {
+            match name {
+                "strings" => Ok(Field::Strings),
+                "value" => Ok(Field::Value),
+                _ => Err(E::unknown_field(name, FIELDS)),
+            }
+        }
+    }
+
+    // First pass
+
+    /// A serializer that discards everything but the strings of a value, which it adds to the table.
+    #[derive(Clone, Copy)]
+    struct StringCollector<'t> {
+        table: &'t RefCell<SerializedStrings>,
+    }
+
+    #[derive(Debug)]
+    struct CollectError(String);
+
+    impl fmt::Display for CollectError {
+        fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str(&self.0)
+        }
+    }
+
+    impl ser::StdError for CollectError {}
+
+    impl ser::Error for CollectError {
+        fn custom<M: fmt::Display>(message: M) -> Self {
+            CollectError(message.to_string())
+        }
+    }
+
+    macro_rules! discard {
+        ($($method:ident($type:ty)),* $(,)?) => {
+            $(fn $method(self, _: $type) -> Result<(), CollectError> {
+                Ok(())
+            })*
+        };
+    }
+
+    impl<'t> Serializer for StringCollector<'t> {
+        type Ok = ();
+        type Error = CollectError;
+        type SerializeSeq = Self;
+        type SerializeTuple = Self;
+        type SerializeTupleStruct = Self;
+        type SerializeTupleVariant = Self;
+        type SerializeMap = Self;
+        type SerializeStruct = Self;
+        type SerializeStructVariant = Self;
+
+        discard! {
+            serialize_bool(bool), serialize_char(char), serialize_bytes(&[u8]),
+            serialize_i8(i8), serialize_i16(i16), serialize_i32(i32), serialize_i64(i64), serialize_i128(i128),
+            serialize_u8(u8), serialize_u16(u16), serialize_u32(u32), serialize_u64(u64), serialize_u128(u128),
+            serialize_f32(f32), serialize_f64(f64), serialize_unit_struct(&'static str),
+        }
+
+        fn serialize_str(self, string: &str) -> Result<(), CollectError> {
+            self.table.borrow_mut().insert(string)
+        }
+
+        fn serialize_none(self) -> Result<(), CollectError> {
+            Ok(())
+        }
+
+        fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), CollectError> {
+            value.serialize(self)
+        }
+
+        fn serialize_unit(self) -> Result<(), CollectError> {
+            Ok(())
+        }
+
+        fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> Result<(), CollectError> {
+            Ok(())
+        }
+
+        fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _: &'static str, value: &T) -> Result<(), CollectError> {
+            value.serialize(self)
+        }
+
+        fn serialize_newtype_variant<T: ?Sized + Serialize>(
+            self,
+            _: &'static str,
+            _: u32,
+            _: &'static str,
+            value: &T,
+        ) -> Result<(), CollectError> {
+            value.serialize(self)
+        }
+
+        fn serialize_seq(self, _: Option<usize>) -> Result<Self, CollectError> {
+            Ok(self)
+        }
+
+        fn serialize_tuple(self, _: usize) -> Result<Self, CollectError> {
+            Ok(self)
+        }
+
+        fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, CollectError> {
+            Ok(self)
+        }
+
+        fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self, CollectError> {
+            Ok(self)
+        }
+
+        fn serialize_map(self, _: Option<usize>) -> Result<Self, CollectError> {
+            Ok(self)
+        }
+
+        fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, CollectError> {
+            Ok(self)
+        }
+
+        fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self, CollectError> {
+            Ok(self)
+        }
+    }
+
+    macro_rules! collect_compound {
+        ($($trait:ident::$method:ident),* $(,)?) => {
+            $(impl<'t> ser::$trait for StringCollector<'t> {
+                type Ok = ();
+                type Error = CollectError;
+
+                fn $method<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), CollectError> {
+                    value.serialize(*self)
+                }
+
+                fn end(self) -> Result<(), CollectError> {
+                    Ok(())
+                }
+            })*
+        };
+    }
+
+    collect_compound! {
+        SerializeSeq::serialize_element,
+        SerializeTuple::serialize_element,
+        SerializeTupleStruct::serialize_field,
+        SerializeTupleVariant::serialize_field,
+    }
+
+    impl<'t> ser::SerializeMap for StringCollector<'t> {
+        type Ok = ();
+        type Error = CollectError;
+
+        fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), CollectError> {
+            key.serialize(*self)
+        }
+
+        fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), CollectError> {
+            value.serialize(*self)
+        }
+
+        fn end(self) -> Result<(), CollectError> {
+            Ok(())
+        }
+    }
+
+    impl<'t> ser::SerializeStruct for StringCollector<'t> {
+        type Ok = ();
+        type Error = CollectError;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, _: &'static str, value: &T) -> Result<(), CollectError> {
+            value.serialize(*self)
+        }
+
+        fn end(self) -> Result<(), CollectError> {
+            Ok(())
+        }
+    }
+
+    impl<'t> ser::SerializeStructVariant for StringCollector<'t> {
+        type Ok = ();
+        type Error = CollectError;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, _: &'static str, value: &T) -> Result<(), CollectError> {
+            value.serialize(*self)
+        }
+
+        fn end(self) -> Result<(), CollectError> {
+            Ok(())
+        }
+    }
+
+    // Second pass and deserialization
+
+    /// A value or a seed, with the table its strings are replaced by the indexes of.
+    /// The serializers and deserializers of the value are wrapped in a [`TableSerializer`] or a [`TableDeserializer`],
+    /// so that only the strings of this value use the table, not the ones serialized by unrelated serializers.
+    struct WithTable<'t, V, T: ?Sized> {
+        value: V,
+        table: &'t T,
+    }
+
+    impl<V: ?Sized + Serialize> Serialize for WithTable<'_, &V, SerializedStrings> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.value.serialize(TableSerializer { inner: serializer, table: self.table })
+        }
+    }
+
+    impl<'de, V: DeserializeSeed<'de>> DeserializeSeed<'de> for WithTable<'_, V, [IString]> {
+        type Value = V::Value;
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+            self.value.deserialize(TableDeserializer { inner: deserializer, table: self.table })
+        }
+    }
+
+    struct TableSerializer<'t, S> {
+        inner: S,
+        table: &'t SerializedStrings,
+    }
+
+    macro_rules! forward_serialize {
+        ($($method:ident($($arg:ident: $type:ty),*)),* $(,)?) => {
+            $(fn $method(self, $($arg: $type),*) -> Result<S::Ok, S::Error> {
+                self.inner.$method($($arg),*)
+            })*
+        };
+    }
+
+    macro_rules! wrap_compound {
+        ($($method:ident($($arg:ident: $type:ty),*) -> $compound:ident),* $(,)?) => {
+            $(fn $method(self, $($arg: $type),*) -> Result<Self::$compound, S::Error> {
+                let table = self.table;
+                self.inner.$method($($arg),*).map(|inner| TableSerializer { inner, table })
+            })*
+        };
+    }
+
+    impl<'t, S: Serializer> Serializer for TableSerializer<'t, S> {
+        type Ok = S::Ok;
+        type Error = S::Error;
+        type SerializeSeq = TableSerializer<'t, S::SerializeSeq>;
+        type SerializeTuple = TableSerializer<'t, S::SerializeTuple>;
+        type SerializeTupleStruct = TableSerializer<'t, S::SerializeTupleStruct>;
+        type SerializeTupleVariant = TableSerializer<'t, S::SerializeTupleVariant>;
+        type SerializeMap = TableSerializer<'t, S::SerializeMap>;
+        type SerializeStruct = TableSerializer<'t, S::SerializeStruct>;
+        type SerializeStructVariant = TableSerializer<'t, S::SerializeStructVariant>;
+
+        forward_serialize! {
+            serialize_bool(v: bool), serialize_char(v: char), serialize_bytes(v: &[u8]),
+            serialize_i8(v: i8), serialize_i16(v: i16), serialize_i32(v: i32), serialize_i64(v: i64), serialize_i128(v: i128),
+            serialize_u8(v: u8), serialize_u16(v: u16), serialize_u32(v: u32), serialize_u64(v: u64), serialize_u128(v: u128),
+            serialize_f32(v: f32), serialize_f64(v: f64), serialize_none(), serialize_unit(),
+            serialize_unit_struct(name: &'static str),
+            serialize_unit_variant(name: &'static str, variant_index: u32, variant: &'static str),
+        }
+
+        fn serialize_str(self, string: &str) -> Result<S::Ok, S::Error> {
+            self.inner.serialize_u32(self.table.index_of(string)?)
+        }
+
+        fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<S::Ok, S::Error> {
+            self.inner.serialize_some(&WithTable { value, table: self.table })
+        }
+
+        fn serialize_newtype_struct<T: ?Sized + Serialize>(self, name: &'static str, value: &T) -> Result<S::Ok, S::Error> {
+            self.inner.serialize_newtype_struct(name, &WithTable { value, table: self.table })
+        }
+
+        fn serialize_newtype_variant<T: ?Sized + Serialize>(
+            self,
+            name: &'static str,
+            variant_index: u32,
+            variant: &'static str,
+            value: &T,
+        ) -> Result<S::Ok, S::Error> {
+            self.inner.serialize_newtype_variant(name, variant_index, variant, &WithTable { value, table: self.table })
+        }
+
+        wrap_compound! {
+            serialize_seq(len: Option<usize>) -> SerializeSeq,
+            serialize_tuple(len: usize) -> SerializeTuple,
+            serialize_tuple_struct(name: &'static str, len: usize) -> SerializeTupleStruct,
+            serialize_tuple_variant(name: &'static str, variant_index: u32, variant: &'static str, len: usize) -> SerializeTupleVariant,
+            serialize_map(len: Option<usize>) -> SerializeMap,
+            serialize_struct(name: &'static str, len: usize) -> SerializeStruct,
+            serialize_struct_variant(name: &'static str, variant_index: u32, variant: &'static str, len: usize) -> SerializeStructVariant,
+        }
+
+        fn is_human_readable(&self) -> bool {
+            self.inner.is_human_readable()
+        }
+    }
+
+    macro_rules! table_compound {
+        ($($trait:ident::$method:ident),* $(,)?) => {
+            $(impl<S: ser::$trait> ser::$trait for TableSerializer<'_, S> {
+                type Ok = S::Ok;
+                type Error = S::Error;
+
+                fn $method<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+                    self.inner.$method(&WithTable { value, table: self.table })
+                }
+
+                fn end(self) -> Result<S::Ok, S::Error> {
+                    self.inner.end()
+                }
+            })*
+        };
+    }
+
+    table_compound! {
+        SerializeSeq::serialize_element,
+        SerializeTuple::serialize_element,
+        SerializeTupleStruct::serialize_field,
+        SerializeTupleVariant::serialize_field,
+    }
+
+    impl<S: ser::SerializeMap> ser::SerializeMap for TableSerializer<'_, S> {
+        type Ok = S::Ok;
+        type Error = S::Error;
+
+        fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), S::Error> {
+            self.inner.serialize_key(&WithTable { value: key, table: self.table })
+        }
+
+        fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+            self.inner.serialize_value(&WithTable { value, table: self.table })
+        }
+
+        fn end(self) -> Result<S::Ok, S::Error> {
+            self.inner.end()
+        }
+    }
+
+    impl<S: ser::SerializeStruct> ser::SerializeStruct for TableSerializer<'_, S> {
+        type Ok = S::Ok;
+        type Error = S::Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), S::Error> {
+            self.inner.serialize_field(key, &WithTable { value, table: self.table })
+        }
+
+        fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+            self.inner.skip_field(key)
+        }
+
+        fn end(self) -> Result<S::Ok, S::Error> {
+            self.inner.end()
+        }
+    }
+
+    impl<S: ser::SerializeStructVariant> ser::SerializeStructVariant for TableSerializer<'_, S> {
+        type Ok = S::Ok;
+        type Error = S::Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), S::Error> {
+            self.inner.serialize_field(key, &WithTable { value, table: self.table })
+        }
+
+        fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+            self.inner.skip_field(key)
+        }
+
+        fn end(self) -> Result<S::Ok, S::Error> {
+            self.inner.end()
+        }
+    }
+
+    /// Wraps a deserializer, a visitor, or the accesses given to a visitor, so that the table follows the value.
+    struct TableDeserializer<'t, D> {
+        inner: D,
+        table: &'t [IString],
+    }
+
+    impl<'t, D> TableDeserializer<'t, D> {
+        fn wrap<T>(&self, inner: T) -> TableDeserializer<'t, T> {
+            TableDeserializer { inner, table: self.table }
+        }
+    }
+
+    macro_rules! forward_deserialize {
+        ($($method:ident($($arg:ident: $type:ty),*)),* $(,)?) => {
+            $(fn $method<V: Visitor<'de>>(self, $($arg: $type,)* visitor: V) -> Result<V::Value, D::Error> {
+                let visitor = self.wrap(visitor);
+                self.inner.$method($($arg,)* visitor)
+            })*
+        };
+    }
+
+    impl<'de, D: Deserializer<'de>> Deserializer<'de> for TableDeserializer<'_, D> {
+        type Error = D::Error;
+
+        forward_deserialize! {
+            deserialize_any(), deserialize_bool(), deserialize_char(), deserialize_bytes(), deserialize_byte_buf(),
+            deserialize_i8(), deserialize_i16(), deserialize_i32(), deserialize_i64(), deserialize_i128(),
+            deserialize_u8(), deserialize_u16(), deserialize_u32(), deserialize_u64(), deserialize_u128(),
+            deserialize_f32(), deserialize_f64(), deserialize_option(), deserialize_unit(),
+            deserialize_unit_struct(name: &'static str),
+            deserialize_seq(), deserialize_tuple(len: usize), deserialize_tuple_struct(name: &'static str, len: usize),
+            deserialize_map(), deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+            deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+            deserialize_identifier(), deserialize_ignored_any(),
+        }
+
+        fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+            let index = u32::deserialize(self.inner)?;
+            let string = self.table.get(index as usize)
+                .ok_or_else(|| de::Error::custom(format_args!("no string at index {index} of the string table")))?;
+            // Note: an `IString` finds it, since the table retains it
+            visitor.visit_str(string)
+        }
+
+        fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+            self.deserialize_str(visitor)
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, D::Error> {
+            let visitor = self.wrap(visitor);
+            self.inner.deserialize_newtype_struct(name, visitor)
+        }
+
+        fn is_human_readable(&self) -> bool {
+            self.inner.is_human_readable()
+        }
+    }
+
+    macro_rules! forward_visit {
+        ($($method:ident($type:ty)),* $(,)?) => {
+            $(fn $method<E: de::Error>(self, value: $type) -> Result<V::Value, E> {
+                self.inner.$method(value)
+            })*
+        };
+    }
+
+    impl<'de, V: Visitor<'de>> Visitor<'de> for TableDeserializer<'_, V> {
+        type Value = V::Value;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            self.inner.expecting(formatter)
+        }
+
+        forward_visit! {
+            visit_bool(bool), visit_char(char), visit_str(&str), visit_borrowed_str(&'de str), visit_string(String),
+            visit_bytes(&[u8]), visit_borrowed_bytes(&'de [u8]), visit_byte_buf(Vec<u8>),
+            visit_i8(i8), visit_i16(i16), visit_i32(i32), visit_i64(i64), visit_i128(i128),
+            visit_u8(u8), visit_u16(u16), visit_u32(u32), visit_u64(u64), visit_u128(u128),
+            visit_f32(f32), visit_f64(f64),
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+            self.inner.visit_none()
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+            self.inner.visit_unit()
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+            let deserializer = self.wrap(deserializer);
+            self.inner.visit_some(deserializer)
+        }
+
+        fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+            let deserializer = self.wrap(deserializer);
+            self.inner.visit_newtype_struct(deserializer)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+            let seq = self.wrap(seq);
+            self.inner.visit_seq(seq)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+            let map = self.wrap(map);
+            self.inner.visit_map(map)
+        }
+
+        fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+            let data = self.wrap(data);
+            self.inner.visit_enum(data)
+        }
+    }
+
+    impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for TableDeserializer<'_, A> {
+        type Error = A::Error;
+
+        fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, A::Error> {
+            self.inner.next_element_seed(WithTable { value: seed, table: self.table })
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            self.inner.size_hint()
+        }
+    }
+
+    impl<'de, A: MapAccess<'de>> MapAccess<'de> for TableDeserializer<'_, A> {
+        type Error = A::Error;
+
+        fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, A::Error> {
+            self.inner.next_key_seed(WithTable { value: seed, table: self.table })
+        }
+
+        fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+            self.inner.next_value_seed(WithTable { value: seed, table: self.table })
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            self.inner.size_hint()
+        }
+    }
+
+    impl<'t, 'de, A: EnumAccess<'de>> EnumAccess<'de> for TableDeserializer<'t, A> {
+        type Error = A::Error;
+        type Variant = TableDeserializer<'t, A::Variant>;
+
+        fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), A::Error> {
+            let table = self.table;
+            let (value, variant) = self.inner.variant_seed(WithTable { value: seed, table })?;
+            Ok((value, TableDeserializer { inner: variant, table }))
+        }
+    }
+
+    impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for TableDeserializer<'_, A> {
+        type Error = A::Error;
+
+        fn unit_variant(self) -> Result<(), A::Error> {
+            self.inner.unit_variant()
+        }
+
+        fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+            self.inner.newtype_variant_seed(WithTable { value: seed, table: self.table })
+        }
+
+        fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+            let visitor = self.wrap(visitor);
+            self.inner.tuple_variant(len, visitor)
+        }
+
+        fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, A::Error> {
+            let visitor = self.wrap(visitor);
+            self.inner.struct_variant(fields, visitor)
+        }
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    mod tests {
+        use serde::{Deserialize, Serialize};
+
+        use super::*;
+        use crate::tests::with_exclusive_use_of_shared_storage;
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Record {
+            city: IString,
+            country: IString,
+            tags: Vec<IString>,
+            population: u32,
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Snapshot {
+            #[serde(with = "super")]
+            records: Vec<Record>,
+        }
+
+        fn snapshot() -> Snapshot {
+            let records = (0..100)
+                .map(|population| Record {
+                    city: IString::from(["Paris", "Lyon", "Marseille"][population as usize % 3]),
+                    country: IString::from("France"),
+                    tags: vec![IString::from("europe"), IString::from("france")],
+                    population,
+                })
+                .collect();
+            Snapshot { records }
+        }
+
+        #[test]
+        fn it_round_trips_with_json() {
+            with_exclusive_use_of_shared_storage(|| {
+                let snapshot = snapshot();
+                let json = serde_json::to_string(&snapshot).unwrap();
+                assert!(json.starts_with(r#"{"records":{"strings":["Paris","France","europe","france","Lyon","Marseille"],"value":[{"city":0,"#));
+                // the field names are still repeated
+                assert!(json.len() * 3 < serde_json::to_string(&snapshot.records).unwrap().len() * 2);
+
+                assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+                // the tables are only used by the adapter
+                assert_eq!(serde_json::to_string(&IString::from("Paris")).unwrap(), "\"Paris\"");
+                assert_eq!(serde_json::from_str::<IString>("\"Paris\"").unwrap(), IString::from("Paris"));
+            });
+        }
+
+        #[test]
+        fn it_round_trips_with_bincode() {
+            with_exclusive_use_of_shared_storage(|| {
+                let snapshot = snapshot();
+                let bytes = bincode::serialize(&snapshot).unwrap();
+                assert!(bytes.len() * 2 < bincode::serialize(&snapshot.records).unwrap().len());
+
+                assert_eq!(bincode::deserialize::<Snapshot>(&bytes).unwrap(), snapshot);
+            });
+        }
+
+        #[test]
+        fn it_nests_string_tables() {
+            #[derive(Serialize, Deserialize, PartialEq, Debug)]
+            struct Archive {
+                name: IString,
+                #[serde(with = "super")]
+                snapshot: Snapshot,
+            }
+
+            with_exclusive_use_of_shared_storage(|| {
+                let archives = vec![Archive { name: IString::from("France"), snapshot: snapshot() }];
+                let mut json = Vec::new();
+                serialize(&archives, &mut serde_json::Serializer::new(&mut json)).unwrap();
+                let json = String::from_utf8(json).unwrap();
+                // the strings of the inner tables are strings of the outer table too
+                assert!(json.starts_with(r#"{"strings":["France","Paris","europe","france","Lyon","Marseille"],"value":[{"name":0,"snapshot":{"strings":[1,0,2,3,4,5],"value":{"records":{"strings":[0,1,2,3,4,5],"#));
+
+                let deserialized: Vec<Archive> = deserialize(&mut serde_json::Deserializer::from_str(&json)).unwrap();
+                assert_eq!(deserialized, archives);
+            });
+        }
+
+        #[test]
+        fn it_goes_through_options_maps_and_enums() {
+            #[derive(Serialize, Deserialize, PartialEq, Debug)]
+            enum Dish {
+                Named(IString),
+                Menu { starter: Option<IString>, main: (IString, u8) },
+            }
+
+            #[derive(Serialize, Deserialize, PartialEq, Debug)]
+            struct Orders(#[serde(with = "super")] std::collections::BTreeMap<IString, Vec<Dish>>);
+
+            with_exclusive_use_of_shared_storage(|| {
+                let orders = Orders(
+                    [(IString::from("table 1"), vec![
+                        Dish::Named(IString::from("pasta")),
+                        Dish::Menu { starter: Some(IString::from("soup")), main: (IString::from("pasta"), 2) },
+                        Dish::Menu { starter: None, main: (IString::from("table 1"), 1) },
+                    ])]
+                    .into_iter()
+                    .collect(),
+                );
+                let json = serde_json::to_string(&orders).unwrap();
+                assert_eq!(
+                    json,
+                    r#"{"strings":["table 1","pasta","soup"],"value":{"0":[{"Named":1},{"Menu":{"starter":2,"main":[1,2]}},{"Menu":{"starter":null,"main":[0,1]}}]}}"#
+                );
+                assert_eq!(serde_json::from_str::<Orders>(&json).unwrap(), orders);
+                assert_eq!(bincode::deserialize::<Orders>(&bincode::serialize(&orders).unwrap()).unwrap(), orders);
+            });
+        }
+
+        #[test]
+        fn it_only_uses_the_table_with_its_own_serializers() {
+            fn to_json<S: serde::Serializer>(istring: &IString, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&serde_json::to_string(istring).unwrap())
+            }
+
+            fn from_json<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<IString, D::Error> {
+                Ok(serde_json::from_str(&String::deserialize(deserializer)?).unwrap())
+            }
+
+            #[derive(Serialize, Deserialize, PartialEq, Debug)]
+            struct Record {
+                city: IString,
+                #[serde(serialize_with = "to_json", deserialize_with = "from_json")]
+                country: IString,
+            }
+
+            with_exclusive_use_of_shared_storage(|| {
+                let records = vec![Record { city: IString::from("Paris"), country: IString::from("France") }];
+                let mut json = Vec::new();
+                serialize(&records, &mut serde_json::Serializer::new(&mut json)).unwrap();
+                let json = String::from_utf8(json).unwrap();
+                // the IString given to the other serializer is a plain string, that is in the table like any other string
+                assert_eq!(json, r#"{"strings":["Paris","\"France\""],"value":[{"city":0,"country":1}]}"#);
+
+                let deserialized: Vec<Record> = deserialize(&mut serde_json::Deserializer::from_str(&json)).unwrap();
+                assert_eq!(deserialized, records);
+            });
+        }
+
+        #[test]
+        fn it_rejects_invalid_tables() {
+            with_exclusive_use_of_shared_storage(|| {
+                let deserialize = |json| deserialize::<Vec<IString>, _>(&mut serde_json::Deserializer::from_str(json));
+
+                assert_eq!(deserialize(r#"{"strings":["a"],"value":[0,0]}"#).unwrap(), [IString::from("a"), IString::from("a")]);
+                assert!(deserialize(r#"{"strings":["a"],"value":[1]}"#).is_err());
+                assert!(deserialize(r#"{"value":[0],"strings":["a"]}"#).is_err());
+                assert!(deserialize(r#"{"strings":["a"]}"#).is_err());
+            });
+        }
+    }
+}
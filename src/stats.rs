@@ -0,0 +1,45 @@
+use core::time::Duration;
+
+/// A snapshot of the state of the interned strings storage.
+///
+/// # Example
+///
+/// ```
+/// use interned_string::IString;
+///
+/// let hello = IString::from("hello");
+/// let stats = IString::stats();
+/// assert!(stats.strings >= 1);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct Stats {
+    /// The number of stored strings, including the unused strings that are not freed yet.
+    pub strings: usize,
+    /// The number of stored strings that are no longer used and will be freed by the next collection.
+    pub unused_strings: usize,
+    /// The number of bytes occupied by the contents of the stored strings.
+    pub bytes: usize,
+    /// The total length of the stored strings that are no longer used.
+    pub unused_bytes: usize,
+    /// The number of reference count updates that were sent by the readers
+    /// and are not received by the writer yet.
+    ///
+    /// They are applied when a string is inserted or the garbage is collected.
+    /// The readers also receive them themselves when there are a few thousands of them,
+    /// and leave them to the next insert or garbage collection, added up for each string.
+    pub pending_ops: usize,
+    /// The number of distinct keys issued so far.
+    /// Since the keys of freed strings are issued again, this is the highest number of strings
+    /// that were stored at the same time.
+    pub keys_issued: usize,
+    /// The number of garbage collections that found unused strings to free so far.
+    pub gc_runs: u64,
+    /// The number of times a reader received the pending reference count updates
+    /// because there were too many of them.
+    pub reader_drains: u64,
+    /// The number of times the changes were published to the readers, each one waiting for them.
+    pub publishes: u64,
+    /// How long the last publication of the changes took, including the wait for the readers.
+    pub last_publish_duration: Option<Duration>,
+}
@@ -1,76 +1,391 @@
-use core::fmt;
+use alloc::{boxed::Box, collections::{BTreeMap, VecDeque}, string::String, vec::Vec};
+use core::{
+    borrow::Borrow,
+    fmt,
+    mem::MaybeUninit,
+    ops::{Bound, Deref},
+    ptr::NonNull,
+};
+#[cfg(feature = "std")]
 use std::{
     collections::HashMap,
-    mem::MaybeUninit,
-    ops::Deref,
-    sync::Mutex,
+    ptr,
+    sync::{atomic::{AtomicPtr, AtomicUsize, Ordering}, Arc, Mutex, Weak},
+    time::{Duration, Instant},
 };
+#[cfg(feature = "std")]
 use left_right::{Absorb, ReadHandle, WriteHandle};
-use once_cell::sync::Lazy;
-use radix_trie::{Trie, TrieKey};
+#[cfg(feature = "std")]
 use lockfree::channel::{mpsc, RecvErr};
 
-use crate::IString;
+#[cfg(feature = "std")]
+use crate::{gc::GcPolicy, stats::Stats};
+
+#[cfg(not(feature = "std"))]
+pub(crate) mod local;
 
 pub(crate) type IStringKey = u32;
 
+/// The key of a stored string that was found or inserted on behalf of a new `IString`.
+#[derive(Clone, Copy)]
+pub(crate) struct RetainedKey {
+    pub(crate) key: IStringKey,
+    /// When the string is pinned, it was not retained because it is never freed.
+    pub(crate) is_pinned: bool,
+    /// The contents of the string.
+    /// They are never moved, and they are not freed for as long as the string is retained or pinned.
+    pub(crate) contents: NonNull<str>,
+}
+
+impl RetainedKey {
+    fn of(key: IStringKey, stored_string: &StoredString) -> Self {
+        Self { key, is_pinned: stored_string.is_pinned, contents: NonNull::from(stored_string.inner.get_contents()) }
+    }
+}
+
+#[cfg(feature = "std")]
 pub(crate) enum StringStorageOp {
-    /// Insert the string in storage with the given key.
-    Insert { key: IStringKey, string: BoxedStr },
-    /// Increment the `strong_count` of the stored string with the given key.
-    Retain { key: IStringKey },
-    /// Decrement the `strong_count` of the stored string with the given key.
-    Release { key: IStringKey },
-    /// Drop (and eventually free) all stored strings that are no longer used.
+    /// Insert the string in storage with the given key,
+    /// and the generation that distinguishes it from the other strings that had the same key.
+    Insert { key: IStringKey, string: BoxedStr, generation: u64 },
+    /// Add `count` to the `strong_count` of the stored string with the given key.
+    Retain { key: IStringKey, count: usize },
+    /// Subtract `count` from the `strong_count` of the stored string with the given key.
+    Release { key: IStringKey, count: usize },
+    /// Never free the stored string with the given key.
+    Pin { key: IStringKey },
+    /// Unpin all the pinned strings, that must no longer be used, so that they can be freed.
+    #[cfg(test)]
+    UnpinAllStrings,
+    /// Remove all stored strings that are no longer used from the index,
+    /// so that readers can no longer find them and retain them,
+    /// except the given number of strings that were the most recently used.
+    /// With `unused_bytes_above`, only when the unused strings occupy more than this number of bytes,
+    /// which is only known once the pending Release operations are absorbed.
+    UnlinkUnusedStrings { keep_recently_unused: usize, unused_bytes_above: Option<usize> },
+    /// Drop (and eventually free) all unlinked strings that are still unused,
+    /// and put back in the index those that were retained in the meantime.
     DropUnusedStrings,
 }
 
+#[cfg(feature = "std")]
 #[derive(Debug)]
 enum ChannelOp {
     /// Eventually increment the `strong_count` of the stored string with the given key.
     Retain { key: IStringKey },
-    /// Eventually decrement the `strong_count` of the stored string with the given key.
-    Release { key: IStringKey },
+    /// Eventually subtract `count` from the `strong_count` of the stored string with the given key.
+    Release { key: IStringKey, count: usize },
+}
+
+/// The operations of a string that were received from the channel and not appended yet, added up.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct ReceivedOps {
+    retains: usize,
+    releases: usize,
+    /// The number of operations received before the last one.
+    last_received: u64,
 }
 
+/// The maximum number of distinct strings whose releases are kept by a `ThreadLocalReader` before they are sent,
+/// see `ThreadLocalReader::with_pending_releases`.
+#[cfg(feature = "std")]
+const MAX_PENDING_RELEASES: usize = 32;
+
+/// The releases kept by a `ThreadLocalReader`, with their count.
+/// The writer takes them when it drains the channel, so that a thread that is idle doesn't keep its strings alive.
+///
+/// Whoever uses them swaps them out until it is done, so the reader and the writer never wait for each other:
+/// when they are in use, the reader sends its release instead, and the writer takes them the next time.
+#[cfg(feature = "std")]
+struct PendingReleases {
+    releases: AtomicPtr<Vec<(IStringKey, usize)>>,
+}
+
+#[cfg(feature = "std")]
+impl PendingReleases {
+    fn new() -> Self {
+        Self { releases: AtomicPtr::new(Box::into_raw(Box::default())) }
+    }
+
+    /// Calls `f` with the releases, unless they are in use.
+    fn with<R>(&self, f: impl FnOnce(&mut Vec<(IStringKey, usize)>) -> R) -> Option<R> {
+        let releases = self.releases.swap(ptr::null_mut(), Ordering::Acquire);
+        if releases.is_null() {
+            return None;
+        }
+        // Safety: they were swapped out, so they are not used anywhere else until they are stored back
+        let result = f(unsafe { &mut *releases });
+        self.releases.store(releases, Ordering::Release);
+        Some(result)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for PendingReleases {
+    fn drop(&mut self) {
+        let releases = *self.releases.get_mut();
+        if !releases.is_null() {
+            // Safety: they were allocated by `PendingReleases::new` and they are not in use
+            drop(unsafe { Box::from_raw(releases) });
+        }
+    }
+}
+
+/// The number of operations in the channel above which a reader receives them itself,
+/// so that the channel stays bounded even if no string is inserted for a long time,
+/// see `ThreadLocalReader::drain_channel_if_backlogged`.
+#[cfg(feature = "std")]
+pub(crate) const MAX_PENDING_CHANNEL_OPS: usize = 4096;
+
+#[cfg(feature = "std")]
 pub(crate) struct UniqueWriter {
     pub(crate) write_handle: WriteHandle<InnerStringStorage, StringStorageOp>,
+    /// The smallest key that was never issued.
     next_key: IStringKey,
+    /// Keys are always lower than this limit.
+    key_limit: IStringKey,
+    /// Keys of freed strings, that can be issued again.
+    free_keys: Vec<IStringKey>,
+    /// The generation of the next inserted string.
+    next_generation: u64,
+    gc_policy: GcPolicy,
+    inserts_since_last_collection: usize,
     ops_channel_receiver: mpsc::Receiver<ChannelOp>,
+    /// The number of operations sent to the channel and not received yet.
+    pending_channel_ops: Arc<AtomicUsize>,
+    /// The operations of each string that were received from the channel and not appended yet,
+    /// see `UniqueWriter::receive_channel_ops`.
+    received_ops: HashMap<IStringKey, ReceivedOps>,
+    /// The number of operations received from the channel so far.
+    ops_received: u64,
+    /// The pending releases of the readers that keep them, or of the readers that were dropped.
+    readers_pending_releases: Arc<Mutex<Vec<Weak<PendingReleases>>>>,
+    gc_runs: u64,
+    /// The number of times a reader applied the operations of the channel because there were too many of them.
+    reader_drains: u64,
+    publishes: u64,
+    last_publish_duration: Option<Duration>,
 }
 
+#[cfg(feature = "std")]
 impl UniqueWriter {
-    fn do_pending_ops_and_insert(&mut self, string: BoxedStr) -> IStringKey {
+    fn do_pending_ops_and_insert(&mut self, string: BoxedStr) -> RetainedKey {
         // add pending operations
         self.drain_channel_ops();
 
-        // insert
-        let key = self.next_key;
-        // TODO: scan the storage for reusable keys when it overflows, instead of panic'ing
-        self.next_key = self.next_key.checked_add(1).unwrap();
-        self.write_handle.append(StringStorageOp::Insert { key, string });
+        // another thread may have inserted the same string while we were waiting for the lock
+        if let Some(found) = self.find_and_retain(&string) {
+            return found;
+        }
 
-        // drop what is unused
-        self.write_handle.append(StringStorageOp::DropUnusedStrings);
+        let inserted = self.append_insert(string);
+        // publish, and drop what is unused according to the policy
+        self.publish_with_gc_policy();
+        inserted
+    }
+
+    fn do_pending_ops_and_insert_many(&mut self, strings: Vec<BoxedStr>) -> Vec<RetainedKey> {
+        // add pending operations
+        self.drain_channel_ops();
+
+        // the strings inserted by this batch, that can't be found in the storage until they are published
+        let mut inserted: HashMap<&str, RetainedKey> = HashMap::new();
+        let mut retained = Vec::with_capacity(strings.len());
+        for string in strings {
+            if let Some(found) = self.find_and_retain(&string) {
+                retained.push(found);
+            } else if let Some(&duplicate) = inserted.get(&*string) {
+                self.write_handle.append(StringStorageOp::Retain { key: duplicate.key, count: 1 });
+                retained.push(duplicate);
+            } else {
+                let new = self.append_insert(string);
+                // Safety: the string is retained, so its contents live until the end of this function
+                inserted.insert(unsafe { new.contents.as_ref() }, new);
+                retained.push(new);
+            }
+        }
+
+        // publish the inserts and the retains at once,
+        // what is unused is dropped according to the policy by the next insert, that waits for the readers anyway
+        self.publish();
+        retained
+    }
+
+    /// Appends a Retain operation for the stored string with the given contents, unless it's pinned,
+    /// and returns it, if it is stored.
+    fn find_and_retain(&mut self, string: &str) -> Option<RetainedKey> {
+        let found = self.find(string)?;
+        if !found.is_pinned {
+            self.write_handle.append(StringStorageOp::Retain { key: found.key, count: 1 });
+        }
+        Some(found)
+    }
 
-        // block until readers are done
+    /// Appends an Insert operation for the given string, that is not stored yet, and returns it.
+    fn append_insert(&mut self, string: BoxedStr) -> RetainedKey {
+        let key = self.issue_key();
+        // Note: the contents are not moved by moving the `BoxedStr`
+        let contents = NonNull::from(string.get_contents());
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.write_handle.append(StringStorageOp::Insert { key, string, generation });
+        self.inserts_since_last_collection += 1;
+        RetainedKey { key, is_pinned: false, contents }
+    }
+
+    /// Publishes the appended operations, and drops what is unused according to the policy.
+    ///
+    /// The unused strings are unlinked by the same publish,
+    /// and the readers are only waited for again when some strings were unlinked.
+    fn publish_with_gc_policy(&mut self) {
+        match self.gc_policy {
+            GcPolicy::EveryInsert => self.append_unlink(0, None),
+            GcPolicy::EveryNInserts(count) if self.inserts_since_last_collection >= count => self.append_unlink(0, None),
+            GcPolicy::UnusedBytesAbove(bytes) => self.append_unlink(0, Some(bytes)),
+            GcPolicy::KeepRecentlyUnused(count) => self.append_unlink(count, None),
+            GcPolicy::EveryNInserts(_) | GcPolicy::Manual => {},
+        }
+        self.publish();
+        self.drop_unlinked_strings();
+    }
+
+    pub(crate) fn gc_policy(&self) -> GcPolicy {
+        self.gc_policy
+    }
+
+    pub(crate) fn set_gc_policy(&mut self, gc_policy: GcPolicy) {
+        self.gc_policy = gc_policy;
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        let storage = self.write_handle.enter().expect("writer is available");
+        Stats {
+            strings: storage.map.len(),
+            unused_strings: storage.unused_strings,
+            bytes: storage.stored_bytes,
+            unused_bytes: storage.unused_bytes,
+            pending_ops: self.pending_channel_ops.load(Ordering::Relaxed),
+            keys_issued: self.next_key as usize,
+            gc_runs: self.gc_runs,
+            reader_drains: self.reader_drains,
+            publishes: self.publishes,
+            last_publish_duration: self.last_publish_duration,
+        }
+    }
+
+    /// Publishes the appended operations.
+    ///
+    /// It first waits for the readers to leave the copy that it updates, then swaps the copies and returns,
+    /// so readers can still be in the copy that it retires.
+    fn publish(&mut self) {
+        let start = Instant::now();
         self.write_handle.publish();
-        return key;
+        self.publishes += 1;
+        self.last_publish_duration = Some(start.elapsed());
+    }
+
+    fn find(&self, string: &str) -> Option<RetainedKey> {
+        // Note: every operation that modifies the index is published before the writer is released,
+        // so the published copy is up to date.
+        let storage = self.write_handle.enter().expect("writer is available");
+        let key = *storage.index.get(string)?;
+        Some(RetainedKey::of(key, &storage.map[&key]))
+    }
+
+    pub(crate) fn pin(&mut self, key: IStringKey) {
+        self.write_handle.append(StringStorageOp::Pin { key });
+        self.publish();
+    }
+
+    fn issue_key(&mut self) -> IStringKey {
+        if let Some(key) = self.free_keys.pop() {
+            key
+        } else if self.next_key < self.key_limit {
+            let key = self.next_key;
+            self.next_key += 1;
+            key
+        } else {
+            // the key space is exhausted, try to reclaim the keys of unused strings
+            self.drop_unused_strings(0);
+            self.free_keys.pop().unwrap_or_else(|| {
+                panic!("cannot intern more than {} strings at the same time", self.key_limit)
+            })
+        }
+    }
+
+    fn drop_unused_strings(&mut self, keep_recently_unused: usize) {
+        self.append_unlink(keep_recently_unused, None);
+        self.publish();
+        self.drop_unlinked_strings();
+    }
+
+    fn append_unlink(&mut self, keep_recently_unused: usize, unused_bytes_above: Option<usize>) {
+        self.inserts_since_last_collection = 0;
+        self.write_handle.append(StringStorageOp::UnlinkUnusedStrings { keep_recently_unused, unused_bytes_above });
+    }
+
+    /// Drops the strings unlinked by the last publish that are still unused, if there are any.
+    fn drop_unlinked_strings(&mut self) {
+        if self.write_handle.enter().expect("writer is available").unlinked_strings.is_empty() {
+            return;
+        }
+        self.gc_runs += 1;
+
+        // Readers can still be in the copy that was just retired, where the unlinked strings are still in the index.
+        // A publish first waits for the readers of that copy to leave, and a reader that found an unlinked string
+        // sent its Retain operation while it was reading, so after this point it is in the channel.
+        self.publish();
+
+        self.drain_channel_ops();
+        self.write_handle.append(StringStorageOp::DropUnusedStrings);
+        self.publish();
+
+        // the keys of the freed strings can now be issued again
+        let storage = self.write_handle.enter().expect("writer is available");
+        self.free_keys.extend_from_slice(&storage.freed_keys);
     }
 
     pub(crate) fn drain_channel_ops(&mut self) {
+        self.take_readers_pending_releases();
+        self.receive_channel_ops();
+        let mut received_ops: Vec<_> = self.received_ops.drain().collect();
+        // Note: in the order the strings were last released, that the policy keeps the most recently unused ones by
+        received_ops.sort_unstable_by_key(|(_, received)| received.last_received);
+        for (key, received) in received_ops {
+            if received.retains > 0 {
+                self.write_handle.append(StringStorageOp::Retain { key, count: received.retains });
+            }
+            if received.releases > 0 {
+                self.write_handle.append(StringStorageOp::Release { key, count: received.releases });
+            }
+        }
+    }
+
+    /// Receives the operations of the channel, and adds them up for each string,
+    /// so that they take as much memory as the strings at most until they are appended.
+    fn receive_channel_ops(&mut self) {
+        // Note: a message can't be received before the messages that other threads started to send before it,
+        // so the channel can look empty while it has messages.
+        // The operations that were sent before this point are received, in particular the retains that
+        // were sent before the releases that were just taken, which must not be applied without them.
+        let mut sent = self.pending_channel_ops.load(Ordering::Relaxed);
         loop {
             match self.ops_channel_receiver.recv() {
                 Ok(operation) => {
-                    match operation {
-                        ChannelOp::Retain { key } => {
-                            self.write_handle.append(StringStorageOp::Retain { key })
-                        },
-                        ChannelOp::Release { key } => {
-                            self.write_handle.append(StringStorageOp::Release { key })
-                        },
+                    sent = sent.saturating_sub(1);
+                    self.pending_channel_ops.fetch_sub(1, Ordering::Relaxed);
+                    let (key, retains, releases) = match operation {
+                        ChannelOp::Retain { key } => (key, 1, 0),
+                        ChannelOp::Release { key, count } => (key, 0, count),
                     };
+                    let received = self.received_ops.entry(key).or_default();
+                    received.retains += retains;
+                    received.releases += releases;
+                    received.last_received = self.ops_received;
+                    self.ops_received += 1;
                 }
+                Err(RecvErr::NoMessage) if sent > 0 => core::hint::spin_loop(),
                 Err(RecvErr::NoMessage) => {
                     // the channel is empty
                     return;
@@ -83,127 +398,410 @@ impl UniqueWriter {
         }
     }
 
+    /// Appends the releases that the readers did not send yet, unless they are in use.
+    fn take_readers_pending_releases(&mut self) {
+        let mut readers_pending_releases = self.readers_pending_releases.lock().unwrap();
+        readers_pending_releases.retain(|pending_releases| {
+            let Some(pending_releases) = pending_releases.upgrade() else {
+                // the reader sent its releases when it was dropped
+                return false;
+            };
+            for (key, count) in pending_releases.with(core::mem::take).unwrap_or_default() {
+                self.write_handle.append(StringStorageOp::Release { key, count });
+            }
+            true
+        });
+    }
+
+    /// Receives the operations of the channel on behalf of a reader that found too many of them.
+    ///
+    /// It never waits for the readers, the next insert or garbage collection appends and publishes them.
+    fn drain_channel_ops_for_reader(&mut self) {
+        self.reader_drains += 1;
+        self.receive_channel_ops();
+    }
+
     pub(crate) fn collect_garbage(&mut self) {
         // add pending operations
         self.drain_channel_ops();
         // drop what is unused
-        self.write_handle.append(StringStorageOp::DropUnusedStrings);
-        // block until readers are done
-        self.write_handle.publish();
+        self.drop_unused_strings(0);
+    }
+
+    /// Like `collect_garbage`, but keeps the recently unused strings that the policy asks to keep.
+    pub(crate) fn collect_garbage_in_background(&mut self) {
+        // add pending operations
+        self.drain_channel_ops();
+        // drop what is unused
+        self.drop_unused_strings(self.gc_policy.kept_unused_strings());
+    }
+
+    #[cfg(test)]
+    pub(crate) fn unpin_all_strings(&mut self) {
+        self.write_handle.append(StringStorageOp::UnpinAllStrings);
+    }
+
+    /// Restarts issuing keys from 0, and never issues a key greater or equal to `key_limit`.
+    /// The storage must be empty.
+    #[cfg(test)]
+    pub(crate) fn reset_key_space(&mut self, key_limit: IStringKey) {
+        debug_assert!(self.write_handle.enter().unwrap().map.is_empty());
+        self.next_key = 0;
+        self.key_limit = key_limit;
+        self.free_keys.clear();
     }
 }
 
 // Needs to be Sync, so we need to use Mutex
+#[cfg(feature = "std")]
 pub(crate) struct ConcurrentStringStorage {
-    pub(crate) writer: Mutex<UniqueWriter>,
+    // Note: shared with the readers, that drain the channel when it is backlogged
+    pub(crate) writer: Arc<Mutex<UniqueWriter>>,
     pub(crate) read_handle: Mutex<ReadHandle<InnerStringStorage>>,
-    ops_channel_sender: mpsc::Sender<ChannelOp>
+    ops_channel_sender: mpsc::Sender<ChannelOp>,
+    pending_channel_ops: Arc<AtomicUsize>,
+    readers_pending_releases: Arc<Mutex<Vec<Weak<PendingReleases>>>>,
 }
 
+#[cfg(feature = "std")]
 impl ConcurrentStringStorage {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         let (write_handle, read_handle) = left_right::new::<InnerStringStorage, StringStorageOp>();
         let (sender, receiver) = mpsc::create();
+        let pending_channel_ops = Arc::new(AtomicUsize::new(0));
+        let readers_pending_releases = Arc::new(Mutex::new(Vec::new()));
         Self {
-            writer: Mutex::new(UniqueWriter {
+            writer: Arc::new(Mutex::new(UniqueWriter {
                 write_handle,
                 next_key: 0,
+                key_limit: IStringKey::MAX,
+                free_keys: Vec::new(),
+                next_generation: 0,
+                gc_policy: GcPolicy::default(),
+                inserts_since_last_collection: 0,
                 ops_channel_receiver: receiver,
-            }),
+                pending_channel_ops: Arc::clone(&pending_channel_ops),
+                received_ops: HashMap::new(),
+                ops_received: 0,
+                readers_pending_releases: Arc::clone(&readers_pending_releases),
+                gc_runs: 0,
+                reader_drains: 0,
+                publishes: 0,
+                last_publish_duration: None,
+            })),
             read_handle: Mutex::new(read_handle),
             ops_channel_sender: sender,
+            pending_channel_ops,
+            readers_pending_releases,
         }
     }
 
-    pub(crate) fn insert_or_retain(&self, string: String) -> IStringKey {
-        let boxed: BoxedStr = string.into();
-        let found_key: Option<IStringKey> = THREAD_LOCAL_READER.with(|tl_reader: &ThreadLocalReader| {
-            let storage = tl_reader.read_handle.enter().expect("reader is available");
-            if let Some(found_key) = storage.trie.get(&boxed).copied() {
-                tl_reader.retain(found_key);
-                return Some(found_key);
-            }
-            return None;
-        });
-
-        if let Some(key) = found_key {
+    pub(crate) fn insert_or_retain(&self, tl_reader: &ThreadLocalReader, string: String) -> RetainedKey {
+        if let Some(found) = tl_reader.get_and_retain(&string) {
             // string is already in storage
-            return key;
+            found
         } else {
             // string is not in storage yet
-            return self.insert(boxed);
+            self.insert(string.into())
         }
     }
 
-    fn insert(&self, string: BoxedStr) -> IStringKey {
+    /// Inserts the given string, unless another thread inserted it since it was looked up.
+    pub(crate) fn insert(&self, string: BoxedStr) -> RetainedKey {
+        let mut writer = self.writer.lock().unwrap();
+        writer.do_pending_ops_and_insert(string)
+    }
+
+    /// Like `insert_or_retain`, but acquires the lock and waits for readers only once.
+    pub(crate) fn insert_or_retain_many(&self, strings: Vec<String>) -> Vec<RetainedKey> {
+        let strings = strings.into_iter().map(BoxedStr::from).collect();
         let mut writer = self.writer.lock().unwrap();
-        return writer.do_pending_ops_and_insert(string);
+        writer.do_pending_ops_and_insert_many(strings)
     }
 }
 
 // does not need to be Sync nor Send :-)
+#[cfg(feature = "std")]
 pub(crate) struct ThreadLocalReader {
     read_handle: ReadHandle<InnerStringStorage>,
+    writer: Arc<Mutex<UniqueWriter>>,
     ops_channel_sender: mpsc::Sender<ChannelOp>,
+    pending_channel_ops: Arc<AtomicUsize>,
+    /// The releases that were not sent yet, with their count, unless they are sent immediately.
+    /// Sending a release later is always safe, it only keeps the string alive for longer.
+    pending_releases: Option<Arc<PendingReleases>>,
+    /// The maximum number of distinct strings in `pending_releases`.
+    max_pending_releases: usize,
 }
 
+#[cfg(feature = "std")]
 impl ThreadLocalReader {
-    fn from(css: &ConcurrentStringStorage) -> Self {
+    /// Creates a reader that sends the releases immediately.
+    pub(crate) fn from(css: &ConcurrentStringStorage) -> Self {
+        Self::with_max_pending_releases(css, 0)
+    }
+
+    /// Creates a reader that keeps the releases and sends them later.
+    /// It must be dropped when its thread exits.
+    pub(crate) fn with_pending_releases(css: &ConcurrentStringStorage) -> Self {
+        Self::with_max_pending_releases(css, MAX_PENDING_RELEASES)
+    }
+
+    fn with_max_pending_releases(css: &ConcurrentStringStorage, max_pending_releases: usize) -> Self {
+        let pending_releases = (max_pending_releases > 0).then(|| {
+            let pending_releases = Arc::new(PendingReleases::new());
+            css.readers_pending_releases.lock().unwrap().push(Arc::downgrade(&pending_releases));
+            pending_releases
+        });
         Self {
             read_handle: css.read_handle.lock().unwrap().clone(),
+            writer: Arc::clone(&css.writer),
             ops_channel_sender: css.ops_channel_sender.clone(),
+            pending_channel_ops: Arc::clone(&css.pending_channel_ops),
+            pending_releases,
+            max_pending_releases,
         }
     }
 
+    /// Retains the stored string with the given contents and returns its key, if it is stored.
+    pub(crate) fn get_and_retain(&self, string: &str) -> Option<RetainedKey> {
+        self.drain_channel_if_backlogged();
+        let storage = self.read_handle.enter().expect("reader is available");
+        let key = *storage.index.get(string)?;
+        Some(self.retain_found(&storage, key))
+    }
+
+    /// Retains the stored strings that start with the given prefix and returns their keys,
+    /// in lexicographic order.
+    pub(crate) fn get_and_retain_with_prefix(&self, prefix: &str) -> Vec<RetainedKey> {
+        let storage = self.read_handle.enter().expect("reader is available");
+        storage.index.range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(string, _)| string.starts_with(prefix))
+            .map(|(_, key)| self.retain_found(&storage, *key))
+            .collect()
+    }
+
+    /// Retains the longest stored string that is a prefix of the given string and returns its key,
+    /// if there is one.
+    pub(crate) fn get_and_retain_longest_prefix_of(&self, string: &str) -> Option<RetainedKey> {
+        let storage = self.read_handle.enter().expect("reader is available");
+        let key = storage.longest_prefix_of(string)?;
+        Some(self.retain_found(&storage, key))
+    }
+
+    /// Retains the string with the given key that was found in the storage, unless it's pinned.
+    fn retain_found(&self, storage: &InnerStringStorage, key: IStringKey) -> RetainedKey {
+        let retained = RetainedKey::of(key, &storage.map[&key]);
+        if !retained.is_pinned {
+            // Note: the Retain operation must be sent while reading,
+            // see `UniqueWriter::drop_unused_strings`
+            self.retain_while_reading(key);
+        }
+        retained
+    }
+
+    pub(crate) fn contains(&self, string: &str) -> bool {
+        let storage = self.read_handle.enter().expect("reader is available");
+        storage.index.contains_key(string)
+    }
+
+    /// Retains every stored string that can be found and returns its key with its reference count,
+    /// in lexicographic order, from the same copy of the storage.
+    pub(crate) fn get_and_retain_all(&self) -> Vec<(RetainedKey, usize)> {
+        let storage = self.read_handle.enter().expect("reader is available");
+        storage.index.values()
+            .map(|key| {
+                // Note: read before it is retained, and the Retain operation is not applied yet anyway
+                let reference_count = storage.map[key].strong_count.max(0) as usize;
+                (self.retain_found(&storage, *key), reference_count)
+            })
+            .collect()
+    }
+
+    /// Returns the contents of the stored string with the given key.
+    ///
+    /// # Safety
+    ///
+    /// The string with the given key must not be freed for at least 'a,
+    /// because it is retained or pinned.
+    pub(crate) unsafe fn read<'a>(&self, key: IStringKey) -> &'a str {
+        let storage = self.read_handle.enter().expect("reader is available");
+        let stored_string = storage.map.get(&key).expect("a retained or pinned key implies that the storage has its string contents");
+        // Safety: the string is not freed for at least 'a, and its contents are never moved.
+        stored_string.inner.get()
+    }
+
+    /// Returns the generation of the stored string with the given key.
+    /// The string must be retained or pinned.
+    pub(crate) fn generation(&self, key: IStringKey) -> u64 {
+        let storage = self.read_handle.enter().expect("reader is available");
+        storage.map[&key].generation
+    }
+
+    /// Retains the stored string with the given key and returns it,
+    /// if it is still the string of the given generation and it can still be found.
+    pub(crate) fn upgrade(&self, key: IStringKey, generation: u64) -> Option<RetainedKey> {
+        let storage = self.read_handle.enter().expect("reader is available");
+        let stored_string = storage.map.get(&key)?;
+        // Note: an unlinked string can't be retained, like the strings that are not in the index,
+        // see `UniqueWriter::drop_unused_strings`
+        if stored_string.generation != generation || !stored_string.is_linked {
+            return None;
+        }
+        Some(self.retain_found(&storage, key))
+    }
+
     pub(crate) fn retain(&self, key: IStringKey) {
-        self.ops_channel_sender
-            .send(ChannelOp::Retain { key })
-            .expect("the receiver is available");
+        self.drain_channel_if_backlogged();
+        self.retain_while_reading(key);
+    }
+
+    /// Like `retain`, but never waits for the readers, so it can be called while reading.
+    fn retain_while_reading(&self, key: IStringKey) {
+        let cancelled_release = self.pending_releases.as_ref().and_then(|pending_releases| {
+            pending_releases.with(|pending_releases| {
+                let index = pending_releases.iter().position(|(pending_key, _)| *pending_key == key)?;
+                // the string is still counted for a released handle, so it now counts for the retained one
+                let (_, count) = &mut pending_releases[index];
+                *count -= 1;
+                if *count == 0 {
+                    pending_releases.swap_remove(index);
+                }
+                Some(())
+            })?
+        });
+        if cancelled_release.is_none() {
+            self.send(ChannelOp::Retain { key });
+        }
     }
 
-    pub(crate) fn release(&self, istring: &mut IString) {
+    /// Releases the string with the given key, eventually.
+    /// The release is sent with the other pending releases, see `ThreadLocalReader::flush_releases`.
+    pub(crate) fn release(&self, key: IStringKey) {
+        self.drain_channel_if_backlogged();
+        let Some(pending_releases) = &self.pending_releases else {
+            return self.send(ChannelOp::Release { key, count: 1 });
+        };
+        let flushed_releases = pending_releases.with(|pending_releases| {
+            if let Some((_, count)) = pending_releases.iter_mut().find(|(pending_key, _)| *pending_key == key) {
+                *count += 1;
+            } else {
+                pending_releases.push((key, 1));
+            }
+            if pending_releases.len() > self.max_pending_releases {
+                core::mem::take(pending_releases)
+            } else {
+                Vec::new()
+            }
+        });
+        // Note: when the writer is taking the pending releases, this one is sent now
+        for (key, count) in flushed_releases.unwrap_or_else(|| vec![(key, 1)]) {
+            self.send(ChannelOp::Release { key, count });
+        }
+    }
+
+    /// Sends the pending releases.
+    ///
+    /// They are sent when there are too many of them, and when the thread exits.
+    /// Otherwise the writer takes them when it drains the channel.
+    fn flush_releases(&self) {
+        let Some(pending_releases) = &self.pending_releases else {
+            return;
+        };
+        // Note: when the writer is taking them, it appends them
+        for (key, count) in pending_releases.with(core::mem::take).unwrap_or_default() {
+            self.send(ChannelOp::Release { key, count });
+        }
+    }
+
+    /// Receives the operations of the channel when there are too many of them, unless the writer is busy.
+    ///
+    /// Otherwise the channel would grow without bound if no string is inserted,
+    /// since only the writer receives from it.
+    /// It never waits for the readers, see `UniqueWriter::drain_channel_ops_for_reader`.
+    #[inline]
+    fn drain_channel_if_backlogged(&self) {
+        if self.pending_channel_ops.load(Ordering::Relaxed) < MAX_PENDING_CHANNEL_OPS {
+            return;
+        }
+        // Note: if the writer is busy, the next operation of this thread tries again
+        if let Ok(mut writer) = self.writer.try_lock() {
+            writer.drain_channel_ops_for_reader();
+        }
+    }
+
+    fn send(&self, operation: ChannelOp) {
+        // Note: counted before it is sent, so that the count never goes below 0
+        self.pending_channel_ops.fetch_add(1, Ordering::Relaxed);
         self.ops_channel_sender
-            .send(ChannelOp::Release { key: istring.key })
+            .send(operation)
             .expect("the receiver is available");
     }
+}
 
-    pub(crate) fn read<'a>(&self, istring: &'a IString) -> &'a str {
-        let iss = self.read_handle.enter().expect("reader is available");
-        let stored_string = iss.map.get(&istring.key).expect("a valid IString implies that the storage has it's string contents");
-        // Safety: we hold a reference to an IString that lives for 'a
-        //         so the IString won't be dropped for at least 'a
-        //         so the BoxedString we get from storage must live for at least 'a as well.
-        return unsafe { stored_string.inner.get() }
+#[cfg(feature = "std")]
+impl Drop for ThreadLocalReader {
+    fn drop(&mut self) {
+        self.flush_releases();
     }
 }
 
-#[derive(Clone)]
 pub(crate) struct StoredString {
     pub(crate) inner: BoxedStr,
     // Note: can be negative because StringStorageOp::Retain and StringStorageOp::Release
     // are not guaranteeded to be appended in order.
     // When performing StringStorageOp::DropUnusedStrings, it should be >= 0 though.
-    strong_count: isize
+    strong_count: isize,
+    // false when the string was removed from the index by StringStorageOp::UnlinkUnusedStrings,
+    // and the following StringStorageOp::DropUnusedStrings was not absorbed yet.
+    is_linked: bool,
+    // true when the string is never freed.
+    // The `IString`s created after the string was pinned don't retain nor release it.
+    is_pinned: bool,
+    // Set when the string is in InnerStringStorage::recently_unused_strings,
+    // to the stamp of its entry.
+    kept_since: Option<u64>,
+    // Distinguishes this string from the other strings that had the same key, see `crate::WeakIString`.
+    generation: u64,
 }
 
 impl StoredString {
-    fn new(string: BoxedStr) -> Self {
-        Self { inner: string, strong_count: 1 }
+    fn new(string: BoxedStr, generation: u64) -> Self {
+        Self {
+            inner: string,
+            strong_count: 1,
+            is_linked: true,
+            is_pinned: false,
+            kept_since: None,
+            generation,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn clone_with_aliasing(&self) -> Self {
+        Self {
+            inner: self.inner.clone_with_aliasing(),
+            strong_count: self.strong_count,
+            is_linked: self.is_linked,
+            is_pinned: self.is_pinned,
+            kept_since: self.kept_since,
+            generation: self.generation,
+        }
     }
 
     #[inline]
-    fn retain(&mut self) {
-        self.strong_count += 1;
+    fn retain(&mut self, count: usize) {
+        self.strong_count += count as isize;
     }
 
     #[inline]
-    fn release(&mut self) {
-        self.strong_count = self.strong_count.checked_sub(1).unwrap();
+    fn release(&mut self, count: usize) {
+        self.strong_count -= count as isize;
     }
 
     #[inline]
     fn is_droppable(&self) -> bool {
-        self.strong_count == 0
+        self.strong_count == 0 && !self.is_pinned
     }
 }
 
@@ -221,21 +819,28 @@ impl PartialEq for BoxedStr {
 
 impl Eq for BoxedStr {}
 
-impl Clone for BoxedStr {
-    fn clone(&self) -> Self {
-        Self { contents: MaybeUninit::new(self.get_contents().clone()) }
+impl PartialOrd for BoxedStr {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BoxedStr {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        // must be consistent with the order of `str`, see `impl Borrow<str>`
+        self.get_contents().cmp(other.get_contents())
     }
 }
 
 impl BoxedStr {
     #[inline]
-    fn get_contents(&self) -> &Box<str> {
+    fn get_contents(&self) -> &str {
         // Safety: the contents are always init.
         // MaybeUninit<...> is only used to disallow the compiler to assume noalias.
         unsafe { self.contents.assume_init_ref() }
     }
 
-    fn clone_with_aliasing(&mut self) -> Self {
+    fn clone_with_aliasing(&self) -> Self {
         // Safety: this is ok because the contents are always init,
         // and thanks to MaybeUninit<_> the compiler can't assume noalias
         // so it's fine to copy the box (the fat pointer) to make a new BoxedStr.
@@ -244,20 +849,20 @@ impl BoxedStr {
         }
     }
 
-    unsafe fn free(self) {
-        // Calling free() on a BoxedStr that is still being aliased will cause a double free.
-        // The caller must make sure that `self` is the last BoxedStr that is sharing (aliasing) the contents.
-        let contents = self.contents.assume_init();
-        drop(contents);
-    }
-
     unsafe fn get<'a>(&self) -> &'a str {
-        let slice: &str = &self.get_contents().as_ref();
+        let slice: &str = self.get_contents();
         // Safety: this extends the lifetime of `slice` from 'self (the lifetime of the borrowed self)
         // to an arbitrary 'a that the caller chooses.
         // This is unsafe because the caller must manually choose a lifetime that actually does not
         // exceed the lifetime of the `BoxedStr`.
-        std::mem::transmute(slice)
+        core::mem::transmute(slice)
+    }
+
+    unsafe fn free(self) {
+        // Calling free() on a BoxedStr that is still being aliased will cause a double free.
+        // The caller must make sure that `self` is the last BoxedStr that is sharing (aliasing) the contents.
+        let contents = self.contents.assume_init();
+        drop(contents);
     }
 }
 
@@ -265,7 +870,13 @@ impl Deref for BoxedStr {
     type Target = str;
 
     fn deref(&self) -> &Self::Target {
-        self.get_contents().as_ref()
+        self.get_contents()
+    }
+}
+
+impl Borrow<str> for BoxedStr {
+    fn borrow(&self) -> &str {
+        self.get_contents()
     }
 }
 
@@ -281,64 +892,212 @@ impl From<String> for BoxedStr {
     }
 }
 
-#[cfg(test)]
-impl From<&str> for BoxedStr {
-    fn from(value: &str) -> Self {
-        Self { contents: MaybeUninit::new(value.to_string().into_boxed_str()) }
-    }
-}
-
-impl TrieKey for BoxedStr {
-    #[inline]
-    fn encode_bytes(&self) -> Vec<u8> {
-        self.get_contents().encode_bytes()
-    }
-}
+#[cfg(feature = "std")]
+type StoredStrings = HashMap<IStringKey, StoredString>;
+#[cfg(not(feature = "std"))]
+type StoredStrings = BTreeMap<IStringKey, StoredString>;
 
+#[derive(Default)]
 pub(crate) struct InnerStringStorage {
-    pub(crate) trie: Trie<BoxedStr, IStringKey>,
-    pub(crate) map: HashMap<IStringKey, StoredString>,
+    /// The keys of the stored strings that can be found, sorted by contents.
+    /// Its `BoxedStr`s alias the ones of the map, so each string is only stored once.
+    pub(crate) index: BTreeMap<BoxedStr, IStringKey>,
+    pub(crate) map: StoredStrings,
     pub(crate) strings_to_possibly_free: Vec<IStringKey>,
+    /// Unused strings that are not freed yet because they were recently used, from the oldest to the newest,
+    /// with the stamp of the moment they became unused.
+    /// An entry is outdated when its stamp is not the `kept_since` of the stored string.
+    recently_unused_strings: VecDeque<(IStringKey, u64)>,
+    /// The stamp of the next entry of `recently_unused_strings`.
+    next_unused_stamp: u64,
+    /// Strings removed from the index by the last `StringStorageOp::UnlinkUnusedStrings`.
+    unlinked_strings: Vec<IStringKey>,
+    /// Keys of the strings freed by the last `StringStorageOp::DropUnusedStrings`.
+    freed_keys: Vec<IStringKey>,
+    /// The total length of the stored strings.
+    stored_bytes: usize,
+    /// The number of stored strings that are not used nor pinned.
+    unused_strings: usize,
+    /// The total length of the stored strings that are not used nor pinned.
+    pub(crate) unused_bytes: usize,
 }
 
-impl Default for InnerStringStorage {
-    fn default() -> Self {
-        Self {
-            trie: Trie::new(),
-            map: HashMap::new(),
-            strings_to_possibly_free: Vec::new()
-        }
+impl InnerStringStorage {
+    /// Inserts the string in the index and in the map, that owns its contents.
+    fn insert(&mut self, key: IStringKey, string: BoxedStr, generation: u64) {
+        let previous_key = self.index.insert(string.clone_with_aliasing(), key);
+        debug_assert!(
+            previous_key.is_none(),
+            "Inserting a new string '{}' in index but there is already a key {} for it ", &string, previous_key.unwrap()
+        );
+
+        self.stored_bytes += string.len();
+        let previous_stored = self.map.insert(key, StoredString::new(string, generation));
+        debug_assert!(
+            previous_stored.is_none(),
+            "Inserting a new string '{}' in map but an older string '{}' was already set for key {}",
+            &self.map.get(&key).unwrap().inner,
+            previous_stored.unwrap().inner,
+            key
+        );
     }
-}
 
-impl InnerStringStorage {
     #[inline]
-    fn retain(&mut self, key: IStringKey) {
+    fn retain(&mut self, key: IStringKey, count: usize) {
         let stored_string = self.map.get_mut(&key).unwrap();
-        stored_string.retain();
+        if stored_string.is_droppable() {
+            self.unused_strings -= 1;
+            self.unused_bytes -= stored_string.inner.len();
+        }
+        stored_string.retain(count);
+        // Note: can happen because Retain and Release operations are not guaranteed to be absorbed in order
+        if stored_string.is_droppable() {
+            self.unused_strings += 1;
+            self.unused_bytes += stored_string.inner.len();
+            self.strings_to_possibly_free.push(key);
+        }
     }
 
     #[inline]
-    fn release(&mut self, key: IStringKey) {
+    fn release(&mut self, key: IStringKey, count: usize) {
         let stored_string = self.map.get_mut(&key).unwrap();
-        stored_string.release();
         if stored_string.is_droppable() {
+            self.unused_strings -= 1;
+            self.unused_bytes -= stored_string.inner.len();
+        }
+        stored_string.release(count);
+        if stored_string.is_droppable() {
+            self.unused_strings += 1;
+            self.unused_bytes += stored_string.inner.len();
             self.strings_to_possibly_free.push(key);
         }
     }
+
+    #[inline]
+    fn pin(&mut self, key: IStringKey) {
+        let stored_string = self.map.get_mut(&key).unwrap();
+        if stored_string.is_droppable() {
+            self.unused_strings -= 1;
+            self.unused_bytes -= stored_string.inner.len();
+        }
+        stored_string.is_pinned = true;
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    fn unpin_all_strings(&mut self) {
+        for (key, stored_string) in self.map.iter_mut() {
+            if stored_string.is_pinned {
+                stored_string.is_pinned = false;
+                stored_string.strong_count = 0;
+                self.unused_strings += 1;
+                self.unused_bytes += stored_string.inner.len();
+                self.strings_to_possibly_free.push(*key);
+            }
+        }
+    }
+
+    fn unlink_unused_strings(&mut self, keep_recently_unused: usize, unused_bytes_above: Option<usize>) {
+        if unused_bytes_above.is_some_and(|bytes| self.unused_bytes <= bytes) {
+            return;
+        }
+        let map = &mut self.map;
+
+        for string_key in self.strings_to_possibly_free.drain(..) {
+            // Note: the string may have been pushed multiple times, or freed already
+            let Some(stored) = map.get_mut(&string_key) else { continue };
+            debug_assert!(stored.strong_count >= 0, "after all Retain/Release operations are absorbed, it should not be negative");
+            // make sure that the string is actually unused
+            if stored.is_droppable() && stored.is_linked {
+                // Note: if the string was already kept, its previous entry is now outdated
+                let stamp = self.next_unused_stamp;
+                self.next_unused_stamp += 1;
+                stored.kept_since = Some(stamp);
+                self.recently_unused_strings.push_back((string_key, stamp));
+            }
+        }
+
+        // forget the outdated entries and the strings that were used again
+        self.recently_unused_strings.retain(|(string_key, stamp)| {
+            let stored = map.get_mut(string_key).unwrap();
+            if stored.kept_since != Some(*stamp) {
+                return false;
+            }
+            if !stored.is_droppable() {
+                stored.kept_since = None;
+                return false;
+            }
+            true
+        });
+
+        // unlink the strings that were unused for the longest time
+        while self.recently_unused_strings.len() > keep_recently_unused {
+            let (string_key, _) = self.recently_unused_strings.pop_front().unwrap();
+            let stored = map.get_mut(&string_key).unwrap();
+            stored.kept_since = None;
+            let removed_key = self.index.remove(stored.inner.get_contents());
+            debug_assert!(removed_key == Some(string_key));
+            stored.is_linked = false;
+            self.unlinked_strings.push(string_key);
+        }
+    }
+
+    /// Returns the key of the longest string of the index that is a prefix of the given string.
+    fn longest_prefix_of(&self, string: &str) -> Option<IStringKey> {
+        // The longest prefix of `string` is always a prefix of `bound`.
+        let mut bound = string;
+        loop {
+            // the greatest candidate, since every prefix of `bound` is lower or equal to `bound`
+            let (candidate, key) = self.index.range::<str, _>((Bound::Unbounded, Bound::Included(bound))).next_back()?;
+            if bound.starts_with(candidate.get_contents()) {
+                return Some(*key);
+            }
+            // The prefixes of `bound` that are longer than the common prefix are greater than the candidate,
+            // so they are not in the index.
+            let mut common_len = candidate.bytes().zip(bound.bytes()).take_while(|(a, b)| a == b).count();
+            while !bound.is_char_boundary(common_len) {
+                common_len -= 1;
+            }
+            bound = &bound[..common_len];
+        }
+    }
+
+    /// Removes the unlinked strings that are still unused from the map and returns them,
+    /// and puts back in the index those that were retained since they were unlinked.
+    fn take_unused_unlinked_strings(&mut self) -> Vec<StoredString> {
+        self.freed_keys.clear();
+        let mut unused = Vec::new();
+        for string_key in self.unlinked_strings.drain(..) {
+            let stored = self.map.get_mut(&string_key).unwrap();
+            if stored.is_droppable() {
+                self.stored_bytes -= stored.inner.len();
+                self.unused_strings -= 1;
+                self.unused_bytes -= stored.inner.len();
+                unused.push(self.map.remove(&string_key).unwrap());
+                self.freed_keys.push(string_key);
+            } else {
+                // a reader found the string before it was unlinked
+                let previous_key = self.index.insert(stored.inner.clone_with_aliasing(), string_key);
+                debug_assert!(previous_key.is_none());
+                stored.is_linked = true;
+            }
+        }
+        unused
+    }
 }
 
+#[cfg(feature = "std")]
 impl Absorb<StringStorageOp> for InnerStringStorage {
     fn absorb_first(&mut self, operation: &mut StringStorageOp, _other: &Self) {
         match operation {
-            StringStorageOp::Insert { key, string } => {
-                let previous_key = self.trie.insert(string.clone(), *key);
+            StringStorageOp::Insert { key, string, generation } => {
+                let previous_key = self.index.insert(string.clone_with_aliasing(), *key);
                 debug_assert!(
                     previous_key.is_none(),
-                    "Inserting a new string '{}' in tree but there is already a key {} for it ", string, previous_key.unwrap()
+                    "Inserting a new string '{}' in index but there is already a key {} for it ", string, previous_key.unwrap()
                 );
 
-                let stored_string_with_aliasing = StoredString::new(string.clone_with_aliasing());
+                let stored_string_with_aliasing = StoredString::new(string.clone_with_aliasing(), *generation);
+                self.stored_bytes += string.len();
 
                 let previous_stored = self.map.insert(*key, stored_string_with_aliasing);
                 debug_assert!(
@@ -346,91 +1105,73 @@ impl Absorb<StringStorageOp> for InnerStringStorage {
                     "Inserting a new string '{}' in map but a value is already set for key {}", string, *key
                 );
             },
-            StringStorageOp::Retain { key } => self.retain(*key),
-            StringStorageOp::Release { key } => self.release(*key),
+            StringStorageOp::Retain { key, count } => self.retain(*key, *count),
+            StringStorageOp::Release { key, count } => self.release(*key, *count),
+            StringStorageOp::Pin { key } => self.pin(*key),
+            #[cfg(test)]
+            StringStorageOp::UnpinAllStrings => self.unpin_all_strings(),
+            StringStorageOp::UnlinkUnusedStrings { keep_recently_unused, unused_bytes_above } => {
+                self.unlink_unused_strings(*keep_recently_unused, *unused_bytes_above)
+            },
             StringStorageOp::DropUnusedStrings => {
                 // Note:
                 // Since we are in absorb_first, we cant free() the unused `BoxedStr`s because
                 // they are still being aliased by the read map's and the write map's `StoredString`s
-                for string_key in self.strings_to_possibly_free.drain(..) {
-                    let stored = self.map.remove(&string_key).unwrap();
-                    debug_assert!(stored.strong_count >= 0, "after all Retain/Release operations are absorbed, it should not be negative");
-                    // make sure that the string is actually unused
-                    if stored.is_droppable() {
-                        // remove it from the trie as well
-                        let removed_key = self.trie.remove(&stored.inner);
-                        debug_assert!(removed_key == Some(string_key));
-
-                        // Note: we can't free() the BoxedStr here because it's still being aliased
-                        // by the other map. We just drop it, which essentially does a forget()
-                        drop(stored)
-                    } else {
-                        // put the StoredString back in the map.
-                        // we optimise for the "if" branch, so in this "else" branch we do more work: remove + insert.
-                        // otherwise, the "if" branch would have to do get + remove, instead of just remove.
-                        self.map.insert(string_key, stored);
-                    }
-                }
+                // We just drop them, which essentially does a forget()
+                drop(self.take_unused_unlinked_strings());
             }
         }
     }
 
     fn absorb_second(&mut self, operation: StringStorageOp, _other: &Self) {
         match operation {
-            StringStorageOp::Insert { key, string } => {
-                let previous_key = self.trie.insert(string.clone(), key);
-                debug_assert!(
-                    previous_key.is_none(),
-                    "Inserting a new string '{}' in tree but there is already a key {} for it ", &string, previous_key.unwrap()
-                );
-
-                let previous_stored = self.map.insert(key, StoredString::new(string));
-                debug_assert!(
-                    previous_stored.is_none(),
-                    "Inserting a new string '{}' in map but an older string '{}' was already set for key {}",
-                    &self.map.get(&key).unwrap().inner,
-                    previous_stored.unwrap().inner,
-                    key
-                );
+            StringStorageOp::Insert { key, string, generation } => {
+                self.insert(key, string, generation);
+            },
+            StringStorageOp::Retain { key, count } => self.retain(key, count),
+            StringStorageOp::Release { key, count } => self.release(key, count),
+            StringStorageOp::Pin { key } => self.pin(key),
+            #[cfg(test)]
+            StringStorageOp::UnpinAllStrings => self.unpin_all_strings(),
+            StringStorageOp::UnlinkUnusedStrings { keep_recently_unused, unused_bytes_above } => {
+                self.unlink_unused_strings(keep_recently_unused, unused_bytes_above)
             },
-            StringStorageOp::Retain { key } => self.retain(key),
-            StringStorageOp::Release { key } => self.release(key),
             StringStorageOp::DropUnusedStrings => {
-                for string_key in self.strings_to_possibly_free.drain(..) {
-                    let stored = self.map.remove(&string_key).unwrap();
-                    debug_assert!(stored.strong_count >= 0, "after all Retain/Release operations are absorbed, it should not be negative");
-                    // make sure that the string is actually unused
-                    if stored.is_droppable() {
-                        // remove it from the trie as well
-                        let removed_key = self.trie.remove(&stored.inner);
-                        debug_assert!(removed_key == Some(string_key));
-
-                        // Safety:
-                        // Since we are in absorb_second, we can free() the BoxedStr because it's now uniquely
-                        // referenced by the write map's StoredString, because absorbed_first already ran for the given
-                        // operation, and must have dropped the other BoxedStr.
-                        unsafe { stored.inner.free() };
-                    } else {
-                        // put the StoredString back in the map.
-                        // we optimise for the "if" branch, so in this "else" branch we do more work: remove + insert.
-                        // otherwise, the "if" branch would have to do get + remove, instead of just remove.
-                        self.map.insert(string_key, stored);
-                    }
+                for stored in self.take_unused_unlinked_strings() {
+                    // Safety:
+                    // Since we are in absorb_second, we can free() the BoxedStr because it's now uniquely
+                    // referenced by the write map's StoredString, because absorbed_first already ran for the given
+                    // operation, and must have dropped the other BoxedStr.
+                    unsafe { stored.inner.free() };
                 }
             },
         }
     }
 
-    fn sync_with(&mut self, first: &Self) {
-        self.trie = first.trie.clone();
-        self.map = first.map.clone();
+    fn drop_second(self: Box<Self>) {
+        // Safety:
+        // drop_first already ran and dropped the other map, so each BoxedStr is now uniquely referenced
+        // by this map's StoredString.
+        for (_, stored) in self.map {
+            unsafe { stored.inner.free() };
+        }
     }
-}
 
-pub(crate) static SHARED_STORAGE: Lazy<ConcurrentStringStorage> = Lazy::new(|| {
-    ConcurrentStringStorage::new()
-});
-
-thread_local! {
-    pub(crate) static THREAD_LOCAL_READER: ThreadLocalReader = ThreadLocalReader::from(&SHARED_STORAGE);
+    fn sync_with(&mut self, first: &Self) {
+        // alias the strings, so that they are freed only once
+        self.index = first.index.iter()
+            .map(|(string, key)| (string.clone_with_aliasing(), *key))
+            .collect();
+        self.map = first.map.iter()
+            .map(|(key, stored)| (*key, stored.clone_with_aliasing()))
+            .collect();
+        self.strings_to_possibly_free = first.strings_to_possibly_free.clone();
+        self.recently_unused_strings = first.recently_unused_strings.clone();
+        self.next_unused_stamp = first.next_unused_stamp;
+        self.unlinked_strings = first.unlinked_strings.clone();
+        self.freed_keys = first.freed_keys.clone();
+        self.stored_bytes = first.stored_bytes;
+        self.unused_strings = first.unused_strings;
+        self.unused_bytes = first.unused_bytes;
+    }
 }
@@ -0,0 +1,434 @@
+use alloc::{string::String, vec::Vec};
+use core::{
+    cell::{RefCell, RefMut},
+    ops::Bound,
+    ptr::NonNull,
+};
+
+use super::{BoxedStr, IStringKey, InnerStringStorage, RetainedKey};
+use crate::{gc::GcPolicy, stats::Stats};
+
+/// The interner used by `IString` without the `std` feature.
+pub(crate) static SHARED_INTERNER: LocalStringStorage = LocalStringStorage::new();
+
+/// The storage of the interned strings without the `std` feature.
+///
+/// There is only one copy of the storage, so it is both the writer and the reader:
+/// the strings are inserted, retained and released directly, without any channel nor lock.
+/// It is only borrowed by the functions of this storage, that never call each other while borrowing it.
+pub(crate) struct LocalStringStorage {
+    /// Created by the first operation, because the storage can't be created in a `const`.
+    writer: SingleThreaded<Option<LocalWriter>>,
+}
+
+/// A value in a `static`, without the `std` feature.
+///
+/// Without the `std` feature, the crate is single-threaded, so the value is never borrowed by two threads.
+/// An interrupt handler that uses the crate while the value is borrowed makes it panic.
+pub(crate) struct SingleThreaded<T> {
+    value: RefCell<T>,
+}
+
+// Safety: without the `std` feature, the crate must only be used by a single thread,
+// and the `IString`s are neither `Send` nor `Sync`.
+unsafe impl<T> Sync for SingleThreaded<T> {}
+
+impl<T> SingleThreaded<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self { value: RefCell::new(value) }
+    }
+
+    #[inline]
+    pub(crate) fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.value.borrow_mut()
+    }
+}
+
+struct LocalWriter {
+    storage: InnerStringStorage,
+    /// The smallest key that was never issued.
+    next_key: IStringKey,
+    /// Keys are always lower than this limit.
+    key_limit: IStringKey,
+    /// Keys of freed strings, that can be issued again.
+    free_keys: Vec<IStringKey>,
+    /// The generation of the next inserted string.
+    next_generation: u64,
+    gc_policy: GcPolicy,
+    inserts_since_last_collection: usize,
+    gc_runs: u64,
+}
+
+impl LocalWriter {
+    fn new() -> Self {
+        Self {
+            storage: InnerStringStorage::default(),
+            next_key: 0,
+            key_limit: IStringKey::MAX,
+            free_keys: Vec::new(),
+            next_generation: 0,
+            gc_policy: GcPolicy::default(),
+            inserts_since_last_collection: 0,
+            gc_runs: 0,
+        }
+    }
+
+    /// Retains the stored string with the given key, unless it's pinned, and returns it.
+    fn retain_found(&mut self, key: IStringKey) -> RetainedKey {
+        let found = RetainedKey::of(key, &self.storage.map[&key]);
+        if !found.is_pinned {
+            self.storage.retain(key, 1);
+        }
+        found
+    }
+
+    /// Retains the stored string with the given contents, unless it's pinned, and returns it, if it is stored.
+    fn find_and_retain(&mut self, string: &str) -> Option<RetainedKey> {
+        let key = *self.storage.index.get(string)?;
+        Some(self.retain_found(key))
+    }
+
+    /// Inserts the given string, that is not stored yet, and returns it.
+    fn insert(&mut self, string: BoxedStr) -> RetainedKey {
+        let key = self.issue_key();
+        // Note: the contents are not moved by moving the `BoxedStr`
+        let contents = NonNull::from(string.get_contents());
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.storage.insert(key, string, generation);
+        self.inserts_since_last_collection += 1;
+        RetainedKey { key, is_pinned: false, contents }
+    }
+
+    /// Frees what is unused according to the policy, after a string was inserted.
+    fn collect_garbage_with_policy(&mut self) {
+        match self.gc_policy {
+            GcPolicy::EveryInsert => self.drop_unused_strings(0),
+            GcPolicy::EveryNInserts(count) if self.inserts_since_last_collection >= count => {
+                self.drop_unused_strings(0)
+            },
+            GcPolicy::UnusedBytesAbove(bytes) if self.storage.unused_bytes > bytes => {
+                self.drop_unused_strings(0)
+            },
+            GcPolicy::KeepRecentlyUnused(count) => self.drop_unused_strings(count),
+            GcPolicy::EveryNInserts(_) | GcPolicy::UnusedBytesAbove(_) | GcPolicy::Manual => {},
+        }
+    }
+
+    fn issue_key(&mut self) -> IStringKey {
+        if let Some(key) = self.free_keys.pop() {
+            key
+        } else if self.next_key < self.key_limit {
+            let key = self.next_key;
+            self.next_key += 1;
+            key
+        } else {
+            // the key space is exhausted, try to reclaim the keys of unused strings
+            self.drop_unused_strings(0);
+            self.free_keys.pop().unwrap_or_else(|| {
+                panic!("cannot intern more than {} strings at the same time", self.key_limit)
+            })
+        }
+    }
+
+    fn drop_unused_strings(&mut self, keep_recently_unused: usize) {
+        self.inserts_since_last_collection = 0;
+        self.gc_runs += 1;
+
+        self.storage.unlink_unused_strings(keep_recently_unused, None);
+        for stored in self.storage.take_unused_unlinked_strings() {
+            // Safety: the index no longer aliases the contents of the unlinked strings,
+            // so each one is uniquely referenced by its `StoredString`.
+            unsafe { stored.inner.free() };
+        }
+        // the keys of the freed strings can now be issued again
+        self.free_keys.extend_from_slice(&self.storage.freed_keys);
+    }
+}
+
+impl Drop for LocalWriter {
+    fn drop(&mut self) {
+        for (_, stored) in core::mem::take(&mut self.storage.map) {
+            // Safety: the index only aliases the contents of the stored strings, that are owned by the map.
+            unsafe { stored.inner.free() };
+        }
+    }
+}
+
+impl LocalStringStorage {
+    const fn new() -> Self {
+        Self { writer: SingleThreaded::new(None) }
+    }
+
+    /// Calls `f` with the reader of this thread, which is the storage itself.
+    #[inline]
+    pub(crate) fn with_reader<R>(&self, f: impl FnOnce(&Self) -> R) -> R {
+        f(self)
+    }
+
+    /// Calls `f` with the storage borrowed, to read or modify it.
+    #[inline]
+    fn with_writer<R>(&self, f: impl FnOnce(&mut LocalWriter) -> R) -> R {
+        f(self.writer.borrow_mut().get_or_insert_with(LocalWriter::new))
+    }
+
+    pub(crate) fn insert_or_retain(&self, string: String) -> RetainedKey {
+        self.with_writer(|writer| {
+            if let Some(found) = writer.find_and_retain(&string) {
+                // string is already in storage
+                return found;
+            }
+            let inserted = writer.insert(string.into());
+            writer.collect_garbage_with_policy();
+            inserted
+        })
+    }
+
+    /// Like `insert_or_retain`, for a string that was just looked up and not found.
+    /// Looking it up again is cheap, since there is only one copy of the storage.
+    pub(crate) fn insert(&self, string: String) -> RetainedKey {
+        self.insert_or_retain(string)
+    }
+
+    pub(crate) fn insert_or_retain_many(&self, strings: Vec<String>) -> Vec<RetainedKey> {
+        self.with_writer(|writer| {
+            let mut has_inserted = false;
+            let retained = strings.into_iter()
+                .map(|string| match writer.find_and_retain(&string) {
+                    Some(found) => found,
+                    None => {
+                        has_inserted = true;
+                        writer.insert(string.into())
+                    },
+                })
+                .collect();
+            if has_inserted {
+                writer.collect_garbage_with_policy();
+            }
+            retained
+        })
+    }
+
+    pub(crate) fn get_and_retain(&self, string: &str) -> Option<RetainedKey> {
+        self.with_writer(|writer| writer.find_and_retain(string))
+    }
+
+    /// Retains the stored strings that start with the given prefix and returns their keys,
+    /// in lexicographic order.
+    pub(crate) fn get_and_retain_with_prefix(&self, prefix: &str) -> Vec<RetainedKey> {
+        self.with_writer(|writer| {
+            let keys: Vec<IStringKey> = writer.storage.index.range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+                .take_while(|(string, _)| string.starts_with(prefix))
+                .map(|(_, key)| *key)
+                .collect();
+            keys.into_iter().map(|key| writer.retain_found(key)).collect()
+        })
+    }
+
+    /// Retains the longest stored string that is a prefix of the given string and returns its key,
+    /// if there is one.
+    pub(crate) fn get_and_retain_longest_prefix_of(&self, string: &str) -> Option<RetainedKey> {
+        self.with_writer(|writer| {
+            let key = writer.storage.longest_prefix_of(string)?;
+            Some(writer.retain_found(key))
+        })
+    }
+
+    pub(crate) fn contains(&self, string: &str) -> bool {
+        self.with_writer(|writer| writer.storage.index.contains_key(string))
+    }
+
+    /// Retains every stored string and returns its key with its reference count, in lexicographic order.
+    pub(crate) fn get_and_retain_all(&self) -> Vec<(RetainedKey, usize)> {
+        self.with_writer(|writer| {
+            let keys: Vec<(IStringKey, usize)> = writer.storage.index.values()
+                .map(|key| (*key, writer.storage.map[key].strong_count.max(0) as usize))
+                .collect();
+            keys.into_iter().map(|(key, reference_count)| (writer.retain_found(key), reference_count)).collect()
+        })
+    }
+
+    /// Returns the contents of the stored string with the given key.
+    ///
+    /// # Safety
+    ///
+    /// The string with the given key must not be freed for at least 'a,
+    /// because it is retained or pinned.
+    pub(crate) unsafe fn read<'a>(&self, key: IStringKey) -> &'a str {
+        self.with_writer(|writer| {
+            let stored_string = writer.storage.map.get(&key).expect("a retained or pinned key implies that the storage has its string contents");
+            // Safety: the string is not freed for at least 'a, and its contents are never moved.
+            unsafe { stored_string.inner.get() }
+        })
+    }
+
+    /// Returns the generation of the stored string with the given key.
+    /// The string must be retained or pinned.
+    pub(crate) fn generation(&self, key: IStringKey) -> u64 {
+        self.with_writer(|writer| writer.storage.map[&key].generation)
+    }
+
+    /// Retains the stored string with the given key and returns it,
+    /// if it is still the string of the given generation.
+    pub(crate) fn upgrade(&self, key: IStringKey, generation: u64) -> Option<RetainedKey> {
+        self.with_writer(|writer| {
+            let stored_string = writer.storage.map.get(&key)?;
+            (stored_string.generation == generation).then(|| writer.retain_found(key))
+        })
+    }
+
+    pub(crate) fn retain(&self, key: IStringKey) {
+        self.with_writer(|writer| writer.storage.retain(key, 1));
+    }
+
+    pub(crate) fn release(&self, key: IStringKey) {
+        self.with_writer(|writer| writer.storage.release(key, 1));
+    }
+
+    pub(crate) fn pin(&self, key: IStringKey) {
+        self.with_writer(|writer| writer.storage.pin(key));
+    }
+
+    pub(crate) fn collect_garbage_now(&self) {
+        self.with_writer(|writer| writer.drop_unused_strings(0));
+    }
+
+    pub(crate) fn gc_policy(&self) -> GcPolicy {
+        self.with_writer(|writer| writer.gc_policy)
+    }
+
+    pub(crate) fn set_gc_policy(&self, gc_policy: GcPolicy) {
+        self.with_writer(|writer| writer.gc_policy = gc_policy);
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        self.with_writer(|writer| Stats {
+            strings: writer.storage.map.len(),
+            unused_strings: writer.storage.unused_strings,
+            bytes: writer.storage.stored_bytes,
+            unused_bytes: writer.storage.unused_bytes,
+            pending_ops: 0,
+            keys_issued: writer.next_key as usize,
+            gc_runs: writer.gc_runs,
+            reader_drains: 0,
+            publishes: 0,
+            last_publish_duration: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::IString;
+
+    static SHARED_STORAGE_MUTEX: Mutex<()> = Mutex::new(());
+
+    /// Runs the closure while no other test uses the strings of the shared interner,
+    /// and frees its strings afterwards.
+    fn with_exclusive_use_of_shared_storage(closure: fn()) {
+        let guard = SHARED_STORAGE_MUTEX.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        closure();
+        SHARED_INTERNER.collect_garbage_now();
+        drop(guard);
+    }
+
+    #[test]
+    fn it_inserts_and_frees_strings() {
+        let storage = LocalStringStorage::new();
+        let hello1 = storage.insert_or_retain("hello".into());
+        let hello2 = storage.insert_or_retain("hello".into());
+        let world = storage.insert_or_retain("world".into());
+        assert_eq!(hello1.key, hello2.key);
+        assert_ne!(hello1.key, world.key);
+        assert_eq!(storage.stats().strings, 2);
+        assert_eq!(storage.stats().bytes, 10);
+
+        storage.release(hello1.key);
+        storage.collect_garbage_now();
+        assert!(storage.contains("hello"));
+
+        storage.release(hello2.key);
+        storage.collect_garbage_now();
+        assert!(!storage.contains("hello"));
+        assert_eq!(storage.stats().strings, 1);
+        // collected after each insert and twice manually
+        assert_eq!(storage.stats().gc_runs, 4);
+
+        // the key of "hello" is issued again
+        let howdy = storage.insert_or_retain("howdy".into());
+        assert_eq!(howdy.key, hello1.key);
+        assert_eq!(storage.upgrade(hello1.key, storage.generation(hello1.key)).map(|found| found.key), Some(howdy.key));
+    }
+
+    #[test]
+    fn it_retains_all_the_strings() {
+        let storage = LocalStringStorage::new();
+        let retained = storage.insert_or_retain_many(["b", "a", "b"].map(String::from).to_vec());
+        assert_eq!(retained[0].key, retained[2].key);
+
+        let all = storage.get_and_retain_all();
+        let keys: Vec<_> = all.iter().map(|(found, reference_count)| (found.key, *reference_count)).collect();
+        assert_eq!(keys, [(retained[1].key, 1), (retained[0].key, 2)]);
+
+        for found in retained.into_iter().chain(all.into_iter().map(|(found, _)| found)) {
+            storage.release(found.key);
+        }
+        storage.collect_garbage_now();
+        assert_eq!(storage.stats().strings, 0);
+    }
+
+    #[test]
+    fn it_follows_the_gc_policy() {
+        let storage = LocalStringStorage::new();
+        storage.set_gc_policy(GcPolicy::KeepRecentlyUnused(1));
+        for string in ["a", "b", "c"] {
+            let found = storage.insert_or_retain(string.into());
+            storage.release(found.key);
+        }
+        // "c" was released after the last insert
+        assert!(!storage.contains("a"));
+        assert!(storage.contains("b"));
+        assert!(storage.contains("c"));
+    }
+
+    #[test]
+    fn it_interns_istrings() {
+        with_exclusive_use_of_shared_storage(|| {
+            let hello = IString::from("hello");
+            let pinned = IString::from_static("pinned");
+            let clones = [hello.clone(), IString::from(String::from("hello"))];
+            assert_eq!(clones[0], clones[1]);
+            assert_eq!(&*clones[1], "hello");
+            assert_eq!(IString::with_prefix("he"), core::slice::from_ref(&hello));
+
+            drop(hello);
+            drop(clones);
+            IString::collect_garbage_now();
+            assert!(!IString::contains("hello"));
+            assert!(IString::contains("pinned"));
+            assert_eq!(pinned.as_ref(), "pinned");
+            assert_eq!(crate::istr!("pinned"), pinned);
+        });
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_serializes_with_a_string_table() {
+        use crate::serde_table::with_string_table;
+
+        with_exclusive_use_of_shared_storage(|| {
+            let strings = ["a", "b", "a"].map(IString::from);
+            let mut json = Vec::new();
+            with_string_table::serialize(&strings, &mut serde_json::Serializer::new(&mut json)).unwrap();
+            assert_eq!(json, br#"{"strings":["a","b"],"value":[0,1,0]}"#);
+
+            let deserialized: Vec<IString> = with_string_table::deserialize(&mut serde_json::Deserializer::from_slice(&json)).unwrap();
+            assert_eq!(deserialized, strings);
+        });
+    }
+}
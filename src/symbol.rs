@@ -0,0 +1,194 @@
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use core::marker::PhantomData;
+use core::{fmt::Debug, ops::Deref, ptr::NonNull};
+
+use crate::SHARED_INTERNER;
+use crate::storage::IStringKey;
+use crate::IString;
+
+/// A handle to a permanent interned string, that is `Copy` and as small as a `u32`.
+///
+/// Copying and dropping a `Symbol` costs nothing, because the string is pinned: it is never freed,
+/// so it's not reference counted.
+/// Use it when the strings are never freed anyway, for example for the identifiers of a compiler.
+///
+/// Like `IString`, `Symbol` provides `Hash` and `Eq` implementations that run in O(1),
+/// and is ordered lexicographically.
+///
+/// # Example
+///
+/// ```
+/// use interned_string::Symbol;
+///
+/// let main = Symbol::from("main");
+/// let copy = main;
+/// assert_eq!(main, copy);
+/// assert_eq!(main.as_str(), "main");
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    key: IStringKey,
+    /// Without the `std` feature, the strings must not be sent to another thread, like `IString`.
+    #[cfg(not(feature = "std"))]
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Symbol {
+    /// Returns the contents of the string.
+    ///
+    /// This operation runs in O(1) and is lock-free.
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        SHARED_INTERNER.with_reader(|tl_reader| {
+            // Safety: the string is pinned, so it is never freed.
+            unsafe { tl_reader.read(self.key) }
+        })
+    }
+}
+
+impl From<IString> for Symbol {
+    /// Pins the string of the given `IString`.
+    ///
+    /// If the string is not pinned yet, a global lock is acquired.
+    #[inline]
+    fn from(mut istring: IString) -> Self {
+        istring.pin();
+        Self {
+            key: istring.key,
+            #[cfg(not(feature = "std"))]
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl From<String> for Symbol {
+    /// Intern the given `String` as a permanent string.
+    ///
+    /// If the string was already interned and pinned, this operation is lock-free.
+    /// Otherwise, a global lock is acquired.
+    #[inline]
+    fn from(string: String) -> Self {
+        Self::from(IString::from(string))
+    }
+}
+
+impl From<&str> for Symbol {
+    /// Intern the given `&str` as a permanent string.
+    ///
+    /// If the string was already interned and pinned, this operation is lock-free.
+    /// Otherwise, a global lock is acquired.
+    #[inline]
+    fn from(string: &str) -> Self {
+        Self::from(IString::from(string))
+    }
+}
+
+impl From<Symbol> for IString {
+    #[inline]
+    fn from(symbol: Symbol) -> Self {
+        IString { key: symbol.key, is_pinned: true, contents: NonNull::from(symbol.as_str()) }
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialOrd for Symbol {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    /// Compares the contents of the strings, see `IString`.
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        if self.key == other.key {
+            core::cmp::Ordering::Equal
+        } else {
+            self.as_str().cmp(other.as_str())
+        }
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Symbol")
+         .field(&self.as_str())
+         .finish()
+    }
+}
+
+impl core::fmt::Display for Symbol {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::tests::with_exclusive_use_of_shared_storage;
+
+    #[test]
+    fn it_is_a_copy_u32() {
+        assert_eq!(core::mem::size_of::<Symbol>(), core::mem::size_of::<u32>());
+
+        with_exclusive_use_of_shared_storage(|| {
+            let hello = Symbol::from("hello");
+            let copies = [hello; 100];
+            assert!(copies.iter().all(|copy| *copy == hello && copy.as_str() == "hello"));
+            // copies are not reference counted
+            assert_eq!(IString::stats().pending_ops, 0);
+        });
+    }
+
+    #[test]
+    fn it_is_never_freed() {
+        with_exclusive_use_of_shared_storage(|| {
+            let hello = Symbol::from("hello".to_string());
+            drop(IString::from("world"));
+            IString::collect_garbage_now();
+
+            assert_eq!(hello.as_str(), "hello");
+            assert!(IString::contains("hello"));
+            assert!(!IString::contains("world"));
+        });
+    }
+
+    #[test]
+    fn it_converts_from_and_to_istring() {
+        with_exclusive_use_of_shared_storage(|| {
+            let istring = IString::from("hello");
+            let symbol = Symbol::from(istring.clone());
+            assert_eq!(Symbol::from("hello"), symbol);
+            assert_eq!(IString::from(symbol), istring);
+            assert_eq!(IString::from(symbol).deref(), "hello");
+        });
+    }
+
+    #[test]
+    fn it_orders_lexicographically() {
+        with_exclusive_use_of_shared_storage(|| {
+            let mut symbols = ["b", "c", "a"].map(Symbol::from);
+            symbols.sort();
+            assert_eq!(symbols.map(|symbol| symbol.as_str()), ["a", "b", "c"]);
+        });
+    }
+}
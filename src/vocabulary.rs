@@ -0,0 +1,194 @@
+use std::io::{self, Read, Write};
+
+use crate::IString;
+
+/// The first bytes of an exported vocabulary.
+const MAGIC: &[u8; 4] = b"ISTR";
+/// The version of the format, incremented when it changes.
+const VERSION: u32 = 1;
+/// The magic bytes, the version and the number of strings.
+const HEADER_LEN: usize = MAGIC.len() + 4 + 8;
+/// The checksum that ends the vocabulary.
+const CHECKSUM_LEN: usize = 8;
+
+// Warm start
+
+impl IString {
+    /// Writes all the interned strings, so that a later run of the program can [`IString::preload`] them.
+    ///
+    /// The format is versioned and ends with a checksum:
+    /// the magic bytes `ISTR`, the version as a little-endian `u32`, the number of strings as a little-endian `u64`,
+    /// then each string as its length in bytes as a little-endian `u32` followed by its UTF-8 contents,
+    /// and finally the 64-bit FNV-1a hash of all the previous bytes as a little-endian `u64`.
+    ///
+    /// The strings that are no longer used but not freed yet are also written.
+    /// The vocabulary is built in memory from a snapshot of the storage, so this operation is lock-free.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use interned_string::IString;
+    ///
+    /// let hello = IString::from("hello");
+    /// let mut vocabulary = Vec::new();
+    /// IString::export_vocabulary(&mut vocabulary).unwrap();
+    ///
+    /// let preloaded = IString::preload(vocabulary.as_slice()).unwrap();
+    /// assert!(preloaded.contains(&hello));
+    /// ```
+    pub fn export_vocabulary(mut writer: impl Write) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + CHECKSUM_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        // the number of strings is only known once they are written
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+
+        let mut count = 0u64;
+        for (string, _) in IString::interned_strings() {
+            let len = u32::try_from(string.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "an interned string is longer than u32::MAX bytes"))?;
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(string.as_bytes());
+            count += 1;
+        }
+
+        bytes[MAGIC.len() + 4..HEADER_LEN].copy_from_slice(&count.to_le_bytes());
+        let checksum = checksum(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        writer.write_all(&bytes)
+    }
+
+    /// Reads a vocabulary written by [`IString::export_vocabulary`], and interns all its strings.
+    ///
+    /// The whole vocabulary is validated before any string is interned, and the strings are interned
+    /// with [`IString::intern_many`], so the global lock is acquired only once.
+    /// An error of kind `InvalidData` is returned if the vocabulary is truncated, corrupted,
+    /// or was written by an unsupported version.
+    ///
+    /// The strings are returned in the order of the vocabulary.
+    /// They are freed once they are dropped, like any other `IString`, so keep the ones you need,
+    /// or [pin](IString::pin) them.
+    pub fn preload(mut reader: impl Read) -> io::Result<Vec<IString>> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let strings = parse_vocabulary(&bytes)?;
+        Ok(IString::intern_many(strings))
+    }
+}
+
+fn parse_vocabulary(bytes: &[u8]) -> io::Result<Vec<String>> {
+    if bytes.len() < HEADER_LEN + CHECKSUM_LEN || !bytes.starts_with(MAGIC) {
+        return Err(invalid_data("not an interned strings vocabulary"));
+    }
+    let (contents, checksum_bytes) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    let version = u32::from_le_bytes(contents[MAGIC.len()..MAGIC.len() + 4].try_into().unwrap());
+    if version != VERSION {
+        return Err(invalid_data(format!("unsupported vocabulary version {version}, expected {VERSION}")));
+    }
+    if checksum(contents) != u64::from_le_bytes(checksum_bytes.try_into().unwrap()) {
+        return Err(invalid_data("the checksum of the vocabulary doesn't match its contents"));
+    }
+
+    let count = u64::from_le_bytes(contents[MAGIC.len() + 4..HEADER_LEN].try_into().unwrap());
+    let mut remaining = &contents[HEADER_LEN..];
+    // each string takes at least 4 bytes, don't trust the count for the allocation
+    let mut strings = Vec::with_capacity((count as usize).min(remaining.len() / 4));
+    for _ in 0..count {
+        let Some((len, rest)) = remaining.split_first_chunk::<4>() else {
+            return Err(invalid_data("the vocabulary is truncated"));
+        };
+        let len = u32::from_le_bytes(*len) as usize;
+        if rest.len() < len {
+            return Err(invalid_data("the vocabulary is truncated"));
+        }
+        let (string, rest) = rest.split_at(len);
+        let string = std::str::from_utf8(string).map_err(|_| invalid_data("a string of the vocabulary is not UTF-8"))?;
+        strings.push(string.to_string());
+        remaining = rest;
+    }
+    if !remaining.is_empty() {
+        return Err(invalid_data("the vocabulary has more strings than its header says"));
+    }
+    Ok(strings)
+}
+
+fn invalid_data(message: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The 64-bit FNV-1a hash, which is stable across platforms and Rust versions, unlike the `Hasher`s of `std`.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::with_exclusive_use_of_shared_storage;
+
+    #[test]
+    fn it_exports_and_preloads_the_vocabulary() {
+        with_exclusive_use_of_shared_storage(|| {
+            let strings = IString::intern_many(["hello", "", "wörld"]);
+            let mut vocabulary = Vec::new();
+            IString::export_vocabulary(&mut vocabulary).unwrap();
+            assert!(vocabulary.starts_with(b"ISTR\x01\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00\x00"));
+            assert_eq!(vocabulary.len(), HEADER_LEN + 3 * 4 + "hello".len() + "wörld".len() + CHECKSUM_LEN);
+
+            drop(strings);
+            IString::collect_garbage_now();
+            assert!(!IString::contains("hello"));
+
+            let publishes = IString::stats().publishes;
+            let mut preloaded = IString::preload(vocabulary.as_slice()).unwrap();
+            // the strings are interned and published at once
+            assert_eq!(IString::stats().publishes, publishes + 1);
+            preloaded.sort();
+            assert_eq!(preloaded, [IString::from(""), IString::from("hello"), IString::from("wörld")]);
+            assert_eq!(IString::stats().strings, 3);
+        });
+    }
+
+    #[test]
+    fn it_validates_the_vocabulary() {
+        with_exclusive_use_of_shared_storage(|| {
+            let strings = IString::intern_many(["hello", "world"]);
+            let mut vocabulary = Vec::new();
+            IString::export_vocabulary(&mut vocabulary).unwrap();
+            drop(strings);
+            IString::collect_garbage_now();
+
+            let preload = |bytes: &[u8]| IString::preload(bytes).unwrap_err().to_string();
+            assert_eq!(preload(b"hello"), "not an interned strings vocabulary");
+            assert_eq!(preload(&vocabulary[..vocabulary.len() - 1]), "the checksum of the vocabulary doesn't match its contents");
+
+            let mut corrupted = vocabulary.clone();
+            corrupted[HEADER_LEN + 4] ^= 1;
+            assert_eq!(preload(&corrupted), "the checksum of the vocabulary doesn't match its contents");
+
+            let mut next_version = vocabulary.clone();
+            next_version[MAGIC.len()] = 2;
+            assert_eq!(preload(&next_version), "unsupported vocabulary version 2, expected 1");
+
+            // nothing was interned
+            assert!(!IString::contains("hello"));
+            assert!(!IString::contains("world"));
+        });
+    }
+
+    #[test]
+    fn it_validates_the_strings() {
+        let with_checksum = |mut contents: Vec<u8>| {
+            let checksum = checksum(&contents);
+            contents.extend_from_slice(&checksum.to_le_bytes());
+            contents
+        };
+        let header = |count: u64| [MAGIC.as_slice(), &VERSION.to_le_bytes(), &count.to_le_bytes()].concat();
+        let parse = |contents| parse_vocabulary(&with_checksum(contents)).unwrap_err().to_string();
+
+        assert_eq!(parse([header(1), vec![2, 0, 0, 0, b'a']].concat()), "the vocabulary is truncated");
+        assert_eq!(parse([header(1), vec![1, 0, 0, 0, 0xff]].concat()), "a string of the vocabulary is not UTF-8");
+        assert_eq!(parse([header(0), vec![1, 0, 0, 0, b'a']].concat()), "the vocabulary has more strings than its header says");
+        assert_eq!(parse_vocabulary(&with_checksum(header(u64::MAX))).unwrap_err().to_string(), "the vocabulary is truncated");
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(not(feature = "std"))]
+use core::marker::PhantomData;
+
+use crate::SHARED_INTERNER;
+use crate::storage::IStringKey;
+use crate::IString;
+
+/// A reference to an interned string that doesn't keep it from being freed.
+///
+/// It's created by [`IString::downgrade`], and gives back an `IString` with [`WeakIString::upgrade`]
+/// for as long as the string is stored, even if no `IString` uses it anymore.
+/// Use it for caches keyed by interned strings, that should not keep the strings alive.
+///
+/// Creating, copying and dropping a `WeakIString` costs nothing, because it's not reference counted.
+///
+/// # Example
+///
+/// ```
+/// use interned_string::IString;
+///
+/// let hello = IString::from("hello");
+/// let weak = hello.downgrade();
+/// assert_eq!(weak.upgrade(), Some(hello.clone()));
+///
+/// drop(hello);
+/// IString::collect_garbage_now();
+/// assert_eq!(weak.upgrade(), None);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct WeakIString {
+    key: IStringKey,
+    /// The key of a freed string is issued again for a new string, that has another generation.
+    generation: u64,
+    /// Without the `std` feature, the strings must not be sent to another thread, like `IString`.
+    #[cfg(not(feature = "std"))]
+    _not_send: PhantomData<*const ()>,
+}
+
+impl WeakIString {
+    /// Returns the string if it is still stored.
+    ///
+    /// This operation runs in O(1) and is lock-free.
+    #[inline]
+    pub fn upgrade(&self) -> Option<IString> {
+        SHARED_INTERNER.with_reader(|tl_reader| tl_reader.upgrade(self.key, self.generation))
+            .map(IString::from_retained)
+    }
+}
+
+impl IString {
+    /// Creates a [`WeakIString`] that refers to this string, without keeping it from being freed.
+    ///
+    /// This operation runs in O(1) and is lock-free.
+    #[inline]
+    pub fn downgrade(&self) -> WeakIString {
+        let generation = SHARED_INTERNER.with_reader(|tl_reader| tl_reader.generation(self.key));
+        WeakIString {
+            key: self.key,
+            generation,
+            #[cfg(not(feature = "std"))]
+            _not_send: PhantomData,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::SHARED_INTERNER;
+    use crate::tests::with_exclusive_use_of_shared_storage;
+    use crate::Symbol;
+
+    #[test]
+    fn it_upgrades_while_the_string_is_stored() {
+        with_exclusive_use_of_shared_storage(|| {
+            let hello = IString::from("hello");
+            let weak = hello.downgrade();
+            assert_eq!(IString::stats().pending_ops, 0);
+
+            let upgraded = weak.upgrade().unwrap();
+            assert_eq!(upgraded, hello);
+            drop(hello);
+            drop(upgraded);
+
+            // the string is not used anymore, but it is not freed yet
+            IString::set_gc_policy(crate::GcPolicy::Manual);
+            let _world = IString::from("world");
+            assert_eq!(weak.upgrade().as_deref(), Some("hello"));
+
+            IString::collect_garbage_now();
+            assert_eq!(weak.upgrade(), None);
+        });
+    }
+
+    #[test]
+    fn it_does_not_upgrade_to_a_new_string_with_the_same_key() {
+        with_exclusive_use_of_shared_storage(|| {
+            SHARED_INTERNER.storage.writer.lock().unwrap().reset_key_space(1);
+
+            let hello = IString::from("hello");
+            let weak = hello.downgrade();
+            drop(hello);
+
+            // the key of "hello" is reused
+            let world = IString::from("world");
+            assert_eq!(world.key, weak.key);
+            assert_eq!(weak.upgrade(), None);
+            assert_eq!(world.downgrade().upgrade(), Some(world));
+        });
+    }
+
+    #[test]
+    fn it_upgrades_pinned_strings() {
+        with_exclusive_use_of_shared_storage(|| {
+            let keyword = IString::from(Symbol::from("return"));
+            let weak = keyword.downgrade();
+            IString::collect_garbage_now();
+
+            let upgraded = weak.upgrade().unwrap();
+            assert!(upgraded.is_pinned);
+            assert_eq!(upgraded, keyword);
+        });
+    }
+}